@@ -0,0 +1,12 @@
+pub mod rsc_core;
+
+pub mod rsc_blockdata;
+pub mod rsc_crypto;
+pub mod rsc_util;
+pub mod rsc_error;
+
+pub mod rsc_miner;
+pub mod rsc_bank;
+
+#[cfg(test)]
+mod rsc_testing;
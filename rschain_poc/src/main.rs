@@ -1,77 +1,72 @@
-mod rsc_core;
-
-mod rsc_blockdata;
-mod rsc_crypto;
-mod rsc_util;
-
-mod rsc_miner;
-mod rsc_bank;
-
-use rand::{self, Rng};
-
-use openssl::pkey::Private;
-use openssl::{rsa::Rsa, pkey::PKey};
-use rsc_core::shard::Shard;
-
-use crate::rsc_blockdata::BlockData;
-use crate::rsc_blockdata::block_data::{WalletData, TransactionData};
-use crate::rsc_core::block::Block;
-use crate::rsc_util::hash::{ByteHash, Hashable};
-
-fn transaction_block(shard: &Shard, prev_hash: ByteHash, from: ByteHash, private_key: &PKey<Private>, to: ByteHash) -> Block {
+use std::time::Duration;
+
+use rschain_poc::rsc_miner;
+use rschain_poc::rsc_crypto::{signature::Scheme, payload::SigningPayload, keystore::{Keystore, WalletKey}, hd::{self, ExtendedKey, DerivationPath}};
+use rschain_poc::rsc_core::shard::Shard;
+use rschain_poc::rsc_blockdata::BlockData;
+use rschain_poc::rsc_blockdata::block_data::{TransactionData, GenesisData, LedgerModel};
+use rschain_poc::rsc_core::block::Block;
+use rschain_poc::rsc_util::{hash::ByteHash, address::Network, hasher::HashAlgorithm};
+
+const CHAIN_ID: &str = "rschain-devnet";
+const NETWORK: Network = Network::Test;
+const HASHER: HashAlgorithm = HashAlgorithm::Sha256;
+const KEYSTORE_PASSWORD: &str = "devnet";
+
+fn transaction_block(shard: &Shard, prev_hash: ByteHash, from: ByteHash, sequence: u64, private_key: &WalletKey, to: ByteHash) -> Block {
     let data = BlockData::Transaction(TransactionData {
-        from: from.into(),
-        to: to.into(),
+        from: from.to_address(NETWORK),
+        to: to.to_address(NETWORK),
         currency: 1,
         amount: 1.0,
+        lock: None,
     });
 
-    let data_vec: Vec<u8> = (&data).into();
-    let signature = rsc_crypto::signature::sign(private_key, &data_vec).unwrap();
+    let payload = SigningPayload::new(CHAIN_ID).build(sequence, &data);
+    let signature = private_key.sign(&payload).unwrap();
 
     let mut block3a = Block::new(prev_hash, data);
     block3a.signature = hex::encode(signature);
+    block3a.sequence = sequence;
 
-    rsc_miner::mine_block(&shard, block3a).unwrap()
+    rsc_miner::mine_block(shard, block3a).unwrap()
 }
 
-fn wallet_block(shard: &Shard, prev_hash: ByteHash) -> (ByteHash, PKey<Private>, Block) {
-    let keypair = Rsa::generate(1024).unwrap();
-    let private_pem = keypair.private_key_to_pem().unwrap();
-    let public_pem = keypair.public_key_to_pem().unwrap();
-
-    let private_key = PKey::private_key_from_pem(&private_pem).unwrap();
-    let _public_key = PKey::public_key_from_pem(&public_pem).unwrap();
+fn wallet_block(shard: &Shard, prev_hash: ByteHash, master: &ExtendedKey, index: u32, keystore: &Keystore) -> (ByteHash, Block) {
+    let private_key = master.derive(&DerivationPath::wallet(0, index)).unwrap().wallet_key().unwrap();
+    let wallet_hash = keystore.import(&private_key, KEYSTORE_PASSWORD).unwrap();
 
-    let data = WalletData { pubkey: String::from_utf8(public_pem.clone()).unwrap() };
-    let wallet_hash = data.hash();
+    let data = private_key.wallet_data().unwrap();
     let block = rsc_miner::mine_block(shard, Block::new(prev_hash, BlockData::Wallet(data))).unwrap();
 
-    (wallet_hash, private_key, block)
+    (wallet_hash, block)
 }
 
 
 fn push_block(shard: &mut Shard, block: Block) -> ByteHash {
-    let block = rsc_miner::mine_block(&shard, block).unwrap();
+    let block = rsc_miner::mine_block(shard, block).unwrap();
     let block_hash = block.hash;
     println!("PUSH {:?}", shard.push(block));
 
-    return block_hash;
+    block_hash
 }
 
 fn main() {
     let mut shard = Shard::new();
+    let mut keystore = Keystore::open(std::env::temp_dir().join("rschain_keystore"), HASHER).unwrap();
+    let master = ExtendedKey::from_mnemonic(Scheme::Ed25519, &hd::generate_mnemonic(), "").unwrap();
 
-    let bhash1 = push_block(&mut shard, Block::new(ByteHash::new(), BlockData::Empty));
+    let bhash1 = push_block(&mut shard, Block::new(ByteHash::new(), BlockData::Genesis(GenesisData { ledger: LedgerModel::Account, policy: None, chain_id: CHAIN_ID.to_string(), network: NETWORK, hasher: HASHER })));
 
-    let (w1, w1pk, b2) = wallet_block(&shard, bhash1);
+    let (w1, b2) = wallet_block(&shard, bhash1, &master, 0, &keystore);
     let bhash2 = push_block(&mut shard, b2);
 
-    let (w2, w2pk, b3) = wallet_block(&shard, bhash2);
+    let (w2, b3) = wallet_block(&shard, bhash2, &master, 1, &keystore);
     let bhash3 = push_block(&mut shard, b3);
 
-    let block3 = transaction_block(&shard, bhash2, w1, &w1pk, w2);
-    let bhash3 = push_block(&mut shard, block3);
+    keystore.unlock(w1, KEYSTORE_PASSWORD, Duration::from_secs(60)).unwrap();
+    let block4 = transaction_block(&shard, bhash3, w1, 0, keystore.key(w1).unwrap(), w2);
+    let _bhash4 = push_block(&mut shard, block4);
 
     println!("{}", shard);
 }
@@ -0,0 +1,128 @@
+use std::{collections::{BTreeMap, BTreeSet}, sync::atomic::{AtomicU64, Ordering}};
+
+const SUPPLY_TOLERANCE: f64 = 1e-9;
+
+static SUPPLY_MISMATCHES: AtomicU64 = AtomicU64::new(0);
+
+/// Number of supply mismatches detected by all auditors running in `AuditMode::Metric`.
+pub fn supply_mismatches() -> u64 {
+    SUPPLY_MISMATCHES.load(Ordering::Relaxed)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AuditMode {
+    Off,
+    Panic,
+    Metric,
+}
+
+impl Default for AuditMode {
+    fn default() -> Self {
+        if cfg!(debug_assertions) { AuditMode::Panic } else { AuditMode::Metric }
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error("supply mismatch of currency {currency}: expected {expected}, found {found}")]
+pub struct SupplyMismatch {
+    pub currency: u64,
+    pub expected: f64,
+    pub found: f64,
+}
+
+/// Tracks total supply per currency as it's created and destroyed, to check it against what wallets actually hold.
+#[derive(Clone, Default)]
+pub struct SupplyAuditor {
+    pub mode: AuditMode,
+    supply: BTreeMap<u64, f64>,
+}
+
+impl SupplyAuditor {
+    pub fn new(mode: AuditMode) -> SupplyAuditor {
+        SupplyAuditor { mode, supply: BTreeMap::new() }
+    }
+
+    pub fn supply(&self, currency: u64) -> f64 {
+        self.supply.get(&currency).copied().unwrap_or_default()
+    }
+
+    pub fn issue(&mut self, currency: u64, amount: f64) {
+        *self.supply.entry(currency).or_default() += amount;
+    }
+
+    pub fn retire(&mut self, currency: u64, amount: f64) {
+        *self.supply.entry(currency).or_default() -= amount;
+    }
+
+    pub fn find_mismatch(&self, holdings: &BTreeMap<u64, f64>) -> Option<SupplyMismatch> {
+        let currencies: BTreeSet<&u64> = self.supply.keys().chain(holdings.keys()).collect();
+
+        currencies.into_iter().find_map(|currency| {
+            let expected = self.supply(*currency);
+            let found = holdings.get(currency).copied().unwrap_or_default();
+
+            // Written so a non-finite supply or holding, whose difference never compares, is a mismatch too.
+            let within = (expected - found).abs() <= SUPPLY_TOLERANCE * expected.abs().max(1.0);
+            if !within {
+                Some(SupplyMismatch { currency: *currency, expected, found })
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Compares tracked supply against holdings, reacting to a mismatch according to the audit mode.
+    pub fn check(&self, holdings: impl FnOnce() -> BTreeMap<u64, f64>) {
+        if self.mode == AuditMode::Off {
+            return;
+        }
+
+        if let Some(mismatch) = self.find_mismatch(&holdings()) {
+            match self.mode {
+                AuditMode::Panic => panic!("{}", mismatch),
+                _ => { SUPPLY_MISMATCHES.fetch_add(1, Ordering::Relaxed); },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Auditor that issued 100 of currency 1, along with holdings short of it.
+    fn short(mode: AuditMode) -> (SupplyAuditor, BTreeMap<u64, f64>) {
+        let mut auditor = SupplyAuditor::new(mode);
+        auditor.issue(1, 100.0);
+        (auditor, BTreeMap::from([(1, 90.0)]))
+    }
+
+    #[test]
+    #[should_panic(expected = "supply mismatch of currency 1")]
+    fn mismatch_panics_in_panic_mode() {
+        let (auditor, holdings) = short(AuditMode::Panic);
+        auditor.check(|| holdings);
+    }
+
+    #[test]
+    fn mismatch_counted_in_metric_mode() {
+        let (auditor, holdings) = short(AuditMode::Metric);
+        let before = supply_mismatches();
+
+        auditor.check(|| holdings.clone());
+        assert_eq!(supply_mismatches(), before + 1);
+
+        // Balanced holdings count nothing, and neither does a mismatch with auditing off.
+        auditor.check(|| BTreeMap::from([(1, 100.0)]));
+        short(AuditMode::Off).0.check(|| holdings);
+        assert_eq!(supply_mismatches(), before + 1);
+    }
+
+    #[test]
+    fn non_finite_holdings_mismatch() {
+        let (auditor, _) = short(AuditMode::Panic);
+        let mismatch = auditor.find_mismatch(&BTreeMap::from([(1, f64::NAN)])).unwrap();
+        assert_eq!((mismatch.currency, mismatch.expected), (1, 100.0));
+        assert!(auditor.find_mismatch(&BTreeMap::from([(1, 100.0)])).is_none());
+    }
+}
@@ -0,0 +1,27 @@
+use serde::Serialize;
+
+use crate::{rsc_util::hash::ByteHash, rsc_blockdata::block_data::SupplyPolicy};
+
+pub const NATIVE_CURRENCY: u64 = 1;
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Currency {
+    pub id: u64,
+    pub symbol: String,
+    pub decimals: u8,
+    pub issuer: Option<ByteHash>,
+    pub policy: SupplyPolicy,
+}
+
+impl Currency {
+    /// Currency granted on wallet creation, it has no issuer and therefore can't be minted or burned.
+    pub fn native() -> Currency {
+        Currency {
+            id: NATIVE_CURRENCY,
+            symbol: String::from("RSC"),
+            decimals: 2,
+            issuer: None,
+            policy: SupplyPolicy::Fixed,
+        }
+    }
+}
@@ -0,0 +1,195 @@
+use std::collections::BTreeMap;
+
+use crate::rsc_util::hash::ByteHash;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Incoming,
+    Outgoing,
+
+    /// Allowance granted between owner and spender, which doesn't change either balance.
+    Approval,
+}
+
+/// Balance change of a single wallet caused by a block, as reported by the ledger.
+#[derive(Clone, Debug)]
+pub struct Movement {
+    pub wallet: ByteHash,
+    pub counterparty: Option<ByteHash>,
+    pub direction: Direction,
+    pub currency: u64,
+    pub amount: f64,
+}
+
+impl Movement {
+    pub fn incoming(wallet: ByteHash, counterparty: Option<ByteHash>, currency: u64, amount: f64) -> Movement {
+        Movement { wallet, counterparty, direction: Direction::Incoming, currency, amount }
+    }
+
+    pub fn outgoing(wallet: ByteHash, counterparty: Option<ByteHash>, currency: u64, amount: f64) -> Movement {
+        Movement { wallet, counterparty, direction: Direction::Outgoing, currency, amount }
+    }
+
+    pub fn approval(wallet: ByteHash, counterparty: ByteHash, currency: u64, amount: f64) -> Movement {
+        Movement { wallet, counterparty: Some(counterparty), direction: Direction::Approval, currency, amount }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct HistoryEntry {
+    pub height: u64,
+    pub block: ByteHash,
+    pub counterparty: Option<ByteHash>,
+    pub direction: Direction,
+    pub currency: u64,
+    pub amount: f64,
+}
+
+/// Per-wallet list of movements, kept in height order as blocks are connected and disconnected.
+#[derive(Clone, Default)]
+pub struct HistoryIndex {
+    entries: BTreeMap<ByteHash, Vec<HistoryEntry>>,
+}
+
+impl HistoryIndex {
+    pub fn new() -> HistoryIndex {
+        HistoryIndex::default()
+    }
+
+    pub fn connect(&mut self, height: u64, block: ByteHash, movements: Vec<Movement>) {
+        for m in movements {
+            self.entries.entry(m.wallet).or_default().push(HistoryEntry {
+                height,
+                block,
+                counterparty: m.counterparty,
+                direction: m.direction,
+                currency: m.currency,
+                amount: m.amount,
+            });
+        }
+    }
+
+    pub fn disconnect(&mut self, height: u64) {
+        for entries in self.entries.values_mut() {
+            while entries.last().is_some_and(|e| e.height >= height) {
+                entries.pop();
+            }
+        }
+
+        self.entries.retain(|_, entries| !entries.is_empty());
+    }
+
+    pub fn len(&self, wallet: &ByteHash) -> usize {
+        self.entries.get(wallet).map_or(0, |e| e.len())
+    }
+
+    pub fn is_empty(&self, wallet: &ByteHash) -> bool {
+        self.len(wallet) == 0
+    }
+
+    /// Page of wallet history, newest entries first.
+    pub fn history(&self, wallet: &ByteHash, offset: usize, limit: usize) -> Vec<&HistoryEntry> {
+        self.entries
+            .get(wallet)
+            .map(|e| e.iter().rev().skip(offset).take(limit).collect())
+            .unwrap_or_default()
+    }
+
+    /// Wallet history within the inclusive height range, oldest entries first.
+    pub fn history_between(&self, wallet: &ByteHash, from_height: u64, to_height: u64) -> Vec<&HistoryEntry> {
+        self.entries
+            .get(wallet)
+            .map(|e| e.iter().filter(|e| e.height >= from_height && e.height <= to_height).collect())
+            .unwrap_or_default()
+    }
+
+    /// Balances of the wallet after the block at given height was applied, including amounts still locked.
+    pub fn balance_at(&self, wallet: &ByteHash, height: u64) -> BTreeMap<u64, f64> {
+        let mut balance = BTreeMap::<u64, f64>::new();
+        for e in self.entries.get(wallet).into_iter().flatten().take_while(|e| e.height <= height) {
+            match e.direction {
+                Direction::Incoming => *balance.entry(e.currency).or_default() += e.amount,
+                Direction::Outgoing => *balance.entry(e.currency).or_default() -= e.amount,
+                Direction::Approval => {},
+            }
+        }
+
+        balance
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NATIVE: u64 = 1;
+
+    fn hash(byte: u8) -> ByteHash {
+        vec![byte; 32].try_into().unwrap()
+    }
+
+    /// Index of alice receiving one more than the height at each height, paying one back and approving bob at height 2.
+    fn index(alice: ByteHash, bob: ByteHash, heights: std::ops::RangeInclusive<u64>) -> HistoryIndex {
+        let mut index = HistoryIndex::new();
+        for height in heights {
+            let mut movements = vec![Movement::incoming(alice, Some(bob), NATIVE, height as f64 + 1.0)];
+            if height == 2 {
+                movements.push(Movement::outgoing(alice, Some(bob), NATIVE, 1.0));
+                movements.push(Movement::approval(alice, bob, NATIVE, 50.0));
+            }
+
+            index.connect(height, hash(height as u8), movements);
+        }
+
+        index
+    }
+
+    fn amounts(entries: Vec<&HistoryEntry>) -> Vec<f64> {
+        entries.into_iter().map(|e| e.amount).collect()
+    }
+
+    #[test]
+    fn disconnect_drops_entries_from_height_onwards() {
+        let (alice, bob) = (hash(101), hash(102));
+        let mut index = index(alice, bob, 1..=3);
+        assert_eq!(index.len(&alice), 5);
+
+        // Reorg back onto height 1, then connect the replacement block at height 2.
+        index.disconnect(2);
+        assert_eq!(amounts(index.history(&alice, 0, 10)), vec![2.0]);
+
+        index.connect(2, hash(42), vec![Movement::outgoing(alice, Some(bob), NATIVE, 0.5)]);
+        let entries = index.history(&alice, 0, 10);
+        assert_eq!((entries[0].block, entries[0].direction), (hash(42), Direction::Outgoing));
+        assert_eq!(index.history_between(&alice, 2, 3).len(), 1);
+
+        index.disconnect(0);
+        assert!(index.is_empty(&alice));
+    }
+
+    #[test]
+    fn history_pages_newest_first() {
+        let (alice, bob) = (hash(101), hash(102));
+        let index = index(alice, bob, 1..=3);
+
+        assert_eq!(amounts(index.history(&alice, 0, 2)), vec![4.0, 50.0]);
+        assert_eq!(amounts(index.history(&alice, 2, 2)), vec![1.0, 3.0]);
+        assert_eq!(amounts(index.history(&alice, 4, 2)), vec![2.0]);
+        assert!(index.history(&alice, 5, 2).is_empty());
+        assert!(index.history(&alice, 0, 0).is_empty());
+        assert!(index.history(&hash(103), 0, 10).is_empty());
+    }
+
+    #[test]
+    fn balance_at_sums_movements_up_to_height() {
+        let (alice, bob) = (hash(101), hash(102));
+        let index = index(alice, bob, 1..=3);
+
+        assert!(index.balance_at(&alice, 0).is_empty());
+        assert_eq!(index.balance_at(&alice, 1), BTreeMap::from([(NATIVE, 2.0)]));
+        // Approvals leave the balance alone.
+        assert_eq!(index.balance_at(&alice, 2), BTreeMap::from([(NATIVE, 4.0)]));
+        assert_eq!(index.balance_at(&alice, u64::MAX), BTreeMap::from([(NATIVE, 8.0)]));
+        assert!(index.balance_at(&bob, 3).is_empty());
+    }
+}
@@ -0,0 +1,22 @@
+use serde::Serialize;
+
+use crate::{rsc_util::hash::ByteHash, rsc_blockdata::block_data::Lock};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum HtlcState {
+    Locked,
+    Claimed,
+    Refunded,
+}
+
+/// Funds held in escrow until claimed with the hashlock preimage or refunded after the timeout.
+#[derive(Clone, Debug, Serialize)]
+pub struct Htlc {
+    pub from: ByteHash,
+    pub to: ByteHash,
+    pub currency: u64,
+    pub amount: f64,
+    pub hashlock: ByteHash,
+    pub timeout: Lock,
+    pub state: HtlcState,
+}
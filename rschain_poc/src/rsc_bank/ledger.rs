@@ -0,0 +1,33 @@
+use std::fmt::Display;
+
+use crate::{rsc_core::block::Block, rsc_blockdata::block_data::{GenesisData, LedgerModel}, rsc_error::{Result, ValidationError}};
+
+use super::history::Movement;
+
+/// State that blocks of a chain are applied to. Which implementation a chain uses is declared by its genesis block.
+pub trait Ledger: Clone + Default + Display {
+    const MODEL: LedgerModel;
+
+    fn do_block(&mut self, block: &Block) -> Result<()>;
+    fn undo_block(&mut self, block: &Block) -> Result<()>;
+
+    /// Verifies the signatures of the blocks ahead of applying them, without touching the state.
+    ///
+    /// Failures are not reported here, applying the block reports them.
+    fn prevalidate(&self, _blocks: &[&Block]) {}
+
+    /// Balance changes the block would cause, evaluated against the state before it is applied.
+    fn movements(&self, block: &Block) -> Vec<Movement>;
+
+    fn check_genesis(&self, height: u64, data: &GenesisData) -> Result<()> {
+        if height != 0 {
+            Err(ValidationError::GenesisMisplaced { height })?;
+        }
+
+        if data.ledger != Self::MODEL {
+            Err(ValidationError::LedgerModelMismatch { expected: Self::MODEL, found: data.ledger })?;
+        }
+
+        Ok(())
+    }
+}
@@ -1,150 +1,1248 @@
-use std::{collections::HashMap, fmt::Display};
+pub mod audit;
+pub mod currency;
+pub mod history;
+pub mod htlc;
+pub mod ledger;
+pub mod policy;
+pub mod stake;
+pub mod utxo;
 
-use openssl::pkey::PKey;
+#[cfg(test)]
+mod tests;
 
-use crate::{rsc_util::hash::{ByteHash, Hashable}, rsc_blockdata::{block_data::{TransactionData, WalletData}, BlockData}, rsc_crypto, rsc_core::block::Block};
+use std::{collections::{BTreeMap, BTreeSet}, fmt::Display, sync::Arc};
+
+use serde::{Serialize, Serializer};
+use sha2::{Sha256, Digest};
+
+use crate::{rsc_util::{hash::{ByteHash, Hashable}, address::{Address, Network}, hasher::HashAlgorithm}, rsc_blockdata::{block_data::{TransactionData, BatchTransferData, ApproveData, TransferFromData, WalletData, MultisigWalletData, RotateKeyData, IssueCurrencyData, MintData, BurnData, StakeData, UnstakeData, SupplyPolicy, Lock, HtlcLockData, HtlcClaimData, HtlcRefundData, LedgerModel, GenesisData}, BlockData}, rsc_crypto::{self, signature::{Scheme, Secp256k1}, payload::SigningPayload, verify::{SignatureCache, SignatureJob}}, rsc_core::block::Block, rsc_error::{Result, ErrorCode, ValidationError}};
+
+use self::currency::{Currency, NATIVE_CURRENCY};
+use self::htlc::{Htlc, HtlcState};
+use self::ledger::Ledger;
+use self::history::Movement;
+use self::audit::SupplyAuditor;
+use self::policy::{MonetaryPolicy, FaucetPolicy};
+use self::stake::{StakeRegistry, UNBONDING_PERIOD};
+
+pub const WALLET_GRANT: f64 = 100.0;
 
 #[derive(thiserror::Error, Debug)]
 pub enum BankError {
-    #[error("WalletNotFound")]
-    WalletNotFound,
+    #[error("WalletNotFound: {wallet}")]
+    WalletNotFound { wallet: ByteHash },
+
+    #[error("WalletDuplicate: {wallet}")]
+    WalletDuplicate { wallet: ByteHash },
+
+    #[error("SignatureInvalid: {wallet}")]
+    SignatureInvalid { wallet: ByteHash },
+
+    #[error("InsufficientCurrency: {wallet} needs {required} of currency {currency}, has {available}")]
+    InsufficientCurrency { wallet: ByteHash, currency: u64, required: f64, available: f64 },
+
+    #[error("CurrencyNotFound: {currency}")]
+    CurrencyNotFound { currency: u64 },
+
+    #[error("CurrencyDuplicate: {currency}")]
+    CurrencyDuplicate { currency: u64 },
+
+    #[error("CurrencyNoIssuer: {currency}")]
+    CurrencyNoIssuer { currency: u64 },
+
+    #[error("CurrencyNotMintable: {currency}")]
+    CurrencyNotMintable { currency: u64 },
+
+    #[error("SignerUnknown: {wallet}")]
+    SignerUnknown { wallet: ByteHash },
+
+    #[error("SignerDuplicate: {wallet}")]
+    SignerDuplicate { wallet: ByteHash },
+
+    #[error("SignatureThreshold: {wallet} needs {required} signatures, has {available}")]
+    SignatureThreshold { wallet: ByteHash, required: usize, available: usize },
+
+    #[error("KeyRotationUnsupported: {wallet}")]
+    KeyRotationUnsupported { wallet: ByteHash },
+
+    #[error("KeyHistoryMismatch: {wallet}")]
+    KeyHistoryMismatch { wallet: ByteHash },
+
+    #[error("LockedAmountNotFound: {wallet}")]
+    LockedAmountNotFound { wallet: ByteHash },
+
+    #[error("HtlcNotFound: {htlc}")]
+    HtlcNotFound { htlc: ByteHash },
+
+    #[error("HtlcDuplicate: {htlc}")]
+    HtlcDuplicate { htlc: ByteHash },
+
+    #[error("HtlcSettled: {htlc}")]
+    HtlcSettled { htlc: ByteHash },
+
+    #[error("HtlcPreimageInvalid: {htlc}")]
+    HtlcPreimageInvalid { htlc: ByteHash },
+
+    #[error("HtlcExpired: {htlc} at height {height}")]
+    HtlcExpired { htlc: ByteHash, height: u64 },
+
+    #[error("HtlcNotExpired: {htlc} at height {height}")]
+    HtlcNotExpired { htlc: ByteHash, height: u64 },
+
+    #[error("IssuanceCapExceeded: currency {currency} needs supply of {required}, cap is {cap}")]
+    IssuanceCapExceeded { currency: u64, required: f64, cap: f64 },
+
+    #[error("AllowanceNotFound: {owner} to {spender} of currency {currency}")]
+    AllowanceNotFound { owner: ByteHash, spender: ByteHash, currency: u64 },
+
+    #[error("AllowanceExceeded: {owner} to {spender} needs {required} of currency {currency}, has {available}")]
+    AllowanceExceeded { owner: ByteHash, spender: ByteHash, currency: u64, required: f64, available: f64 },
 
-    #[error("WalletDuplicate")]
-    WalletDuplicate,
+    #[error("InsufficientStake: {wallet} needs {required}, has {available}")]
+    InsufficientStake { wallet: ByteHash, required: f64, available: f64 },
 
-    #[error("WalletNotFound")]
-    SignatureInvalid,
+    #[error("StakeHistoryMismatch: {wallet}")]
+    StakeHistoryMismatch { wallet: ByteHash },
 
-    #[error("InsufficientCurrency")]
-    InsufficientCurrency,
+    #[error("OutputNotFound: {tx}:{index}")]
+    OutputNotFound { tx: ByteHash, index: u32 },
+
+    #[error("OutputDuplicate: {tx}:{index}")]
+    OutputDuplicate { tx: ByteHash, index: u32 },
+
+    #[error("OwnerMismatch: inputs of {wallet} mixed with others")]
+    OwnerMismatch { wallet: ByteHash },
+
+    #[error("Unbalanced: currency {currency} inputs {required}, outputs {available}")]
+    Unbalanced { currency: u64, required: f64, available: f64 },
+
+    #[error("SequenceMismatch: {wallet} expects {expected}, block has {found}")]
+    SequenceMismatch { wallet: ByteHash, expected: u64, found: u64 },
 }
 
-#[derive(Clone)]
+impl ErrorCode for BankError {
+    fn code(&self) -> u32 {
+        match self {
+            BankError::WalletNotFound { .. } => 2001,
+            BankError::WalletDuplicate { .. } => 2002,
+            BankError::SignatureInvalid { .. } => 2003,
+            BankError::InsufficientCurrency { .. } => 2004,
+            BankError::CurrencyNotFound { .. } => 2005,
+            BankError::CurrencyDuplicate { .. } => 2006,
+            BankError::CurrencyNoIssuer { .. } => 2007,
+            BankError::CurrencyNotMintable { .. } => 2008,
+            BankError::SignerUnknown { .. } => 2009,
+            BankError::SignerDuplicate { .. } => 2010,
+            BankError::SignatureThreshold { .. } => 2011,
+            BankError::KeyRotationUnsupported { .. } => 2012,
+            BankError::KeyHistoryMismatch { .. } => 2013,
+            BankError::LockedAmountNotFound { .. } => 2014,
+            BankError::HtlcNotFound { .. } => 2015,
+            BankError::HtlcDuplicate { .. } => 2016,
+            BankError::HtlcSettled { .. } => 2017,
+            BankError::HtlcPreimageInvalid { .. } => 2018,
+            BankError::HtlcExpired { .. } => 2019,
+            BankError::HtlcNotExpired { .. } => 2020,
+            BankError::IssuanceCapExceeded { .. } => 2021,
+            BankError::AllowanceNotFound { .. } => 2022,
+            BankError::AllowanceExceeded { .. } => 2023,
+            BankError::InsufficientStake { .. } => 2024,
+            BankError::StakeHistoryMismatch { .. } => 2025,
+            BankError::OutputNotFound { .. } => 2026,
+            BankError::OutputDuplicate { .. } => 2027,
+            BankError::OwnerMismatch { .. } => 2028,
+            BankError::Unbalanced { .. } => 2029,
+            BankError::SequenceMismatch { .. } => 2030,
+        }
+    }
+}
+
+#[derive(Clone, Serialize)]
+pub struct Multisig {
+    pub pubkeys: Vec<Vec<u8>>,
+    pub threshold: usize,
+}
+
+#[derive(Clone, PartialEq, Serialize)]
+pub struct LockedAmount {
+    pub currency: u64,
+    pub amount: f64,
+    pub lock: Lock,
+}
+
+#[derive(Clone, Serialize)]
 pub struct Wallet {
     pub hash: ByteHash,
+    pub scheme: Scheme,
     pub pubkey: Vec<u8>,
-    pub accounts: HashMap<u64, f64>
+    pub multisig: Option<Multisig>,
+    pub accounts: BTreeMap<u64, f64>,
+    pub locked: Vec<LockedAmount>,
+
+    /// Replaced public keys along with the height they were rotated out at.
+    pub key_history: Vec<(u64, Vec<u8>)>,
+
+    /// Sequence number the next block signed by this wallet has to carry.
+    pub sequence: u64,
 }
 
 impl Wallet {
-    pub fn new(hash: ByteHash, pubkey: Vec<u8>) -> Wallet {
+    pub fn new(hash: ByteHash, scheme: Scheme, pubkey: Vec<u8>) -> Wallet {
         Wallet {
             hash,
+            scheme,
             pubkey,
-            accounts: HashMap::new(),
+            multisig: None,
+            accounts: BTreeMap::new(),
+            locked: vec![],
+            key_history: vec![],
+            sequence: 0,
+        }
+    }
+
+    /// Multisig wallet, whose member keys all belong to the given scheme.
+    pub fn new_multisig(hash: ByteHash, scheme: Scheme, multisig: Multisig) -> Wallet {
+        Wallet {
+            hash,
+            scheme,
+            pubkey: vec![],
+            multisig: Some(multisig),
+            accounts: BTreeMap::new(),
+            locked: vec![],
+            key_history: vec![],
+            sequence: 0,
         }
     }
 
-    pub fn deduct(&mut self, currency: u64, amount: f64) -> anyhow::Result<()> {
-        let account_amount = self.accounts.get_mut(&currency).ok_or(BankError::InsufficientCurrency)?;
-        if *account_amount < amount {
-            return Err(BankError::InsufficientCurrency.into());
+    pub fn deduct(&mut self, currency: u64, amount: f64) -> Result<()> {
+        let available = self.accounts.get(&currency).copied().unwrap_or_default();
+        if available < amount {
+            Err(BankError::InsufficientCurrency { wallet: self.hash, currency, required: amount, available })?;
         }
 
-        *account_amount -= amount;
+        // Emptied accounts are dropped, so undoing a first receipt leaves no trace of it.
+        match available - amount {
+            0.0 => self.accounts.remove(&currency),
+            remaining => self.accounts.insert(currency, remaining),
+        };
         Ok(())
     }
 
     pub fn add(&mut self, currency: u64, amount: f64) {
         self.accounts.entry(currency).and_modify(|a| *a += amount ).or_insert(amount);
     }
+
+    pub fn add_locked(&mut self, locked: LockedAmount) {
+        self.locked.push(locked);
+    }
+
+    pub fn remove_locked(&mut self, locked: &LockedAmount) -> Result<()> {
+        let position = self.locked.iter().rposition(|l| l == locked).ok_or(BankError::LockedAmountNotFound { wallet: self.hash })?;
+        self.locked.remove(position);
+        Ok(())
+    }
+}
+
+/// Serializes a map as a list of entries, for maps keyed by values that can't be JSON object keys.
+pub(crate) fn serialize_entries<K, V, S>(map: &BTreeMap<K, V>, serializer: S) -> std::result::Result<S::Ok, S::Error>
+where
+    K: Serialize,
+    V: Serialize,
+    S: Serializer,
+{
+    serializer.collect_seq(map)
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize)]
 pub struct Bank {
-    pub wallets: HashMap<ByteHash, Wallet>
+    #[serde(serialize_with = "serialize_entries")]
+    pub wallets: BTreeMap<ByteHash, Wallet>,
+    pub currencies: BTreeMap<u64, Currency>,
+    #[serde(serialize_with = "serialize_entries")]
+    pub htlcs: BTreeMap<ByteHash, Htlc>,
+    pub stakes: StakeRegistry,
+    #[serde(skip)]
+    pub auditor: SupplyAuditor,
+    #[serde(skip)]
+    pub policy: Arc<dyn MonetaryPolicy>,
+
+    /// Chain ID set by the genesis block, part of every signed payload.
+    #[serde(skip)]
+    pub chain_id: String,
+
+    /// Network set by the genesis block, which transaction addresses must belong to.
+    #[serde(skip)]
+    pub network: Network,
+
+    /// Hash function set by the genesis block, deriving wallet and HTLC identifiers and the state hash.
+    #[serde(skip)]
+    pub hasher: HashAlgorithm,
+
+    /// Signatures found valid, shared with clones of the bank on other forks.
+    #[serde(skip)]
+    pub signatures: Arc<SignatureCache>,
+
+    /// Number of blocks applied, which is also the height of the next block.
+    pub height: u64,
+
+    /// Locked amounts released by the block at given height, kept to re-lock them on undo.
+    released: BTreeMap<u64, Vec<(ByteHash, LockedAmount)>>,
+
+    /// Allowances per (owner, spender, currency), with replaced ones kept underneath to restore them on undo.
+    #[serde(serialize_with = "serialize_entries")]
+    allowances: BTreeMap<(ByteHash, ByteHash, u64), Vec<f64>>,
+}
+
+impl Default for Bank {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Bank {
     pub fn new() -> Bank {
-        Bank { wallets: HashMap::new() }
+        Self::with_policy(Arc::new(FaucetPolicy::default()))
+    }
+
+    /// Bank using given monetary policy until a genesis block configures another one.
+    pub fn with_policy(policy: Arc<dyn MonetaryPolicy>) -> Bank {
+        let native = Currency::native();
+
+        Bank {
+            wallets: BTreeMap::new(),
+            currencies: BTreeMap::from([(native.id, native)]),
+            htlcs: BTreeMap::new(),
+            stakes: StakeRegistry::default(),
+            auditor: SupplyAuditor::default(),
+            policy,
+            chain_id: String::new(),
+            network: Network::default(),
+            hasher: HashAlgorithm::default(),
+            signatures: Arc::new(SignatureCache::new()),
+            height: 0,
+            released: BTreeMap::new(),
+            allowances: BTreeMap::new(),
+        }
     }
 
-    pub fn get_wallet(&mut self, hash: ByteHash) -> anyhow::Result<&mut Wallet> {
-        self.wallets.get_mut(&hash).ok_or(BankError::WalletNotFound.into())
+    pub fn get_currency(&self, id: u64) -> Result<&Currency> {
+        self.currencies.get(&id).ok_or(BankError::CurrencyNotFound { currency: id }.into())
     }
 
-    pub fn do_block(&mut self, block: &Block) -> anyhow::Result<()> {
-        self.process_block(block, false)
+    /// Canonical JSON of the bank state, identical on every node that applied the same blocks.
+    pub fn serialize_state(&self) -> Vec<u8> {
+        serde_json::to_vec(self).expect("bank serialization")
     }
 
-    pub fn undo_block(&mut self, block: &Block) -> anyhow::Result<()> {
-        self.process_block(block, true)
+    pub fn state_hash(&self) -> ByteHash {
+        self.hasher.digest(self.serialize_state())
     }
 
-    fn process_block(&mut self, block: &Block, invert: bool) -> anyhow::Result<()> {
+    /// Total of every currency held by wallets, including locked and bonded amounts and funds in HTLC escrow.
+    pub fn holdings(&self) -> BTreeMap<u64, f64> {
+        let mut holdings = BTreeMap::<u64, f64>::new();
+
+        for wallet in self.wallets.values() {
+            for (currency, amount) in &wallet.accounts {
+                *holdings.entry(*currency).or_default() += amount;
+            }
+
+            for l in &wallet.locked {
+                *holdings.entry(l.currency).or_default() += l.amount;
+            }
+        }
+
+        for htlc in self.htlcs.values().filter(|h| h.state == HtlcState::Locked) {
+            *holdings.entry(htlc.currency).or_default() += htlc.amount;
+        }
+
+        if self.stakes.total() > 0.0 {
+            *holdings.entry(NATIVE_CURRENCY).or_default() += self.stakes.total();
+        }
+
+        holdings
+    }
+
+    /// Amount the spender may still transfer out of the owner's wallet.
+    pub fn allowance(&self, owner: &ByteHash, spender: &ByteHash, currency: u64) -> f64 {
+        self.allowances
+            .get(&(*owner, *spender, currency))
+            .and_then(|a| a.last().copied())
+            .unwrap_or_default()
+    }
+
+    pub fn get_wallet(&mut self, hash: ByteHash) -> Result<&mut Wallet> {
+        self.wallets.get_mut(&hash).ok_or(BankError::WalletNotFound { wallet: hash }.into())
+    }
+
+    fn check_issuance_cap(&self, currency: u64, amount: f64) -> Result<()> {
+        if let Some(cap) = self.policy.issuance_cap(currency) {
+            let required = self.auditor.supply(currency) + amount;
+            if required > cap {
+                Err(BankError::IssuanceCapExceeded { currency, required, cap })?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Credits the block subsidy set by monetary policy to the coinbase wallet of the block.
+    fn process_subsidy(&mut self, block: &Block, invert: bool) -> Result<()> {
+        let (Some(coinbase), Some((currency, amount))) = (block.coinbase, self.policy.block_subsidy(self.height)) else {
+            return Ok(());
+        };
+
+        if !invert {
+            self.check_issuance_cap(currency, amount)?;
+
+            let wallet = self.wallets.get_mut(&coinbase).ok_or(BankError::WalletNotFound { wallet: coinbase })?;
+            wallet.add(currency, amount);
+            self.auditor.issue(currency, amount);
+        } else {
+            let wallet = self.wallets.get_mut(&coinbase).ok_or(BankError::WalletNotFound { wallet: coinbase })?;
+            wallet.deduct(currency, amount)?;
+            self.auditor.retire(currency, amount);
+        }
+
+        Ok(())
+    }
+
+    fn release_locked(&mut self, block: &Block) {
+        let mut released = Vec::<(ByteHash, LockedAmount)>::new();
+
+        for (hash, wallet) in self.wallets.iter_mut() {
+            let (unlocked, locked) = wallet.locked
+                .drain(..)
+                .partition(|l| l.lock.is_unlocked(self.height, block.timestamp));
+
+            wallet.locked = locked;
+            for l in unlocked {
+                wallet.add(l.currency, l.amount);
+                released.push((*hash, l));
+            }
+        }
+
+        if !released.is_empty() {
+            self.released.insert(self.height, released);
+        }
+    }
+
+    fn restore_locked(&mut self) -> Result<()> {
+        for (hash, l) in self.released.remove(&self.height).unwrap_or_default() {
+            let wallet = self.wallets.get_mut(&hash).ok_or(BankError::WalletNotFound { wallet: hash })?;
+            wallet.deduct(l.currency, l.amount)?;
+            wallet.add_locked(l);
+        }
+
+        Ok(())
+    }
+
+    fn process_block(&mut self, block: &Block, invert: bool) -> Result<()> {
+        let signer = self.signer(&block.data);
+        if let Some(signer) = &signer {
+            self.check_sequence(block, signer, invert)?;
+        }
+
         match &block.data {
+            BlockData::Genesis(data) => self.process_genesis_block(data, invert),
             BlockData::Transaction(data) => self.process_transaction_block(block, data, invert),
+            BlockData::BatchTransfer(data) => self.process_batch_transfer_block(block, data, invert),
+            BlockData::Approve(data) => self.process_approve_block(block, data, invert),
+            BlockData::TransferFrom(data) => self.process_transfer_from_block(block, data, invert),
             BlockData::Wallet(data) => self.process_wallet_block(data, invert),
+            BlockData::MultisigWallet(data) => self.process_multisig_wallet_block(data, invert),
+            BlockData::RotateKey(data) => self.process_rotate_key_block(block, data, invert),
+            BlockData::IssueCurrency(data) => self.process_issue_currency_block(block, data, invert),
+            BlockData::Mint(data) => self.process_mint_block(block, data, invert),
+            BlockData::Burn(data) => self.process_burn_block(block, data, invert),
+            BlockData::Stake(data) => self.process_stake_block(block, data, invert),
+            BlockData::Unstake(data) => self.process_unstake_block(block, data, invert),
+            BlockData::HtlcLock(data) => self.process_htlc_lock_block(block, data, invert),
+            BlockData::HtlcClaim(data) => self.process_htlc_claim_block(block, data, invert),
+            BlockData::HtlcRefund(data) => self.process_htlc_refund_block(block, data, invert),
+            BlockData::UtxoTransaction(_) => Err(ValidationError::UnsupportedBlockData.into()),
 
             _ => Ok(()),
+        }?;
+
+        if let Some(signer) = signer {
+            self.get_wallet(signer)?.sequence = if invert { block.sequence } else { block.sequence + 1 };
         }
+
+        Ok(())
     }
 
-    fn process_wallet_block(&mut self, data: &WalletData, invert: bool) -> anyhow::Result<()> {
-        let hash = data.hash();
+    fn process_genesis_block(&mut self, data: &GenesisData, invert: bool) -> Result<()> {
+        self.check_genesis(self.height, data)?;
+
+        if let (false, Some(config)) = (invert, &data.policy) {
+            self.policy = policy::from_config(config)?;
+        }
+
+        if !invert {
+            self.chain_id = data.chain_id.clone();
+            self.network = data.network;
+            self.hasher = data.hasher;
+        }
+
+        Ok(())
+    }
+
+    fn process_wallet_block(&mut self, data: &WalletData, invert: bool) -> Result<()> {
+        let pubkey: Vec<u8> = data.pubkey.clone().into();
+        self.register_wallet(Wallet::new(data.hash_with(self.hasher), data.scheme, pubkey), invert)
+    }
+
+    fn process_multisig_wallet_block(&mut self, data: &MultisigWalletData, invert: bool) -> Result<()> {
+        let threshold = data.threshold as usize;
+        let unique: BTreeSet<&String> = data.pubkeys.iter().collect();
+
+        if unique.len() != data.pubkeys.len() || threshold == 0 || threshold > unique.len() {
+            Err(ValidationError::MultisigInvalid { signers: data.pubkeys.len(), threshold })?;
+        }
+
+        let pubkeys = data.pubkeys.iter().map(|k| k.clone().into()).collect();
+        self.register_wallet(Wallet::new_multisig(data.hash_with(self.hasher), data.scheme, Multisig { pubkeys, threshold }), invert)
+    }
+
+    fn process_rotate_key_block(&mut self, block: &Block, data: &RotateKeyData, invert: bool) -> Result<()> {
+        let hash: ByteHash = (&data.wallet).try_into()?;
+        let pubkey: Vec<u8> = data.pubkey.clone().into();
+        let payload = SigningPayload::new(&self.chain_id).build(block.sequence, &block.data);
+        let wallet = self.wallets.get_mut(&hash).ok_or(BankError::WalletNotFound { wallet: hash })?;
+
+        if wallet.multisig.is_some() {
+            Err(BankError::KeyRotationUnsupported { wallet: hash })?;
+        }
+
+        if !invert {
+            wallet.scheme.check_public_key(&pubkey)?;
+            Self::check_key_signature(&self.signatures, hash, wallet.scheme, &wallet.pubkey, &payload, &block.signature)?;
+
+            let previous = std::mem::replace(&mut wallet.pubkey, pubkey);
+            wallet.key_history.push((self.height, previous));
+        } else {
+            let (_, previous) = wallet.key_history.last().ok_or(BankError::KeyHistoryMismatch { wallet: hash })?;
+            if wallet.pubkey != pubkey {
+                Err(BankError::KeyHistoryMismatch { wallet: hash })?;
+            }
+
+            Self::check_key_signature(&self.signatures, hash, wallet.scheme, previous, &payload, &block.signature)?;
+
+            let (_, previous) = wallet.key_history.pop().expect("precheck");
+            wallet.pubkey = previous;
+        }
+
+        Ok(())
+    }
+
+    fn register_wallet(&mut self, mut wallet: Wallet, invert: bool) -> Result<()> {
+        let hash = wallet.hash;
+        let grant = self.policy.wallet_grant(&hash);
 
         if !invert {
             if self.wallets.contains_key(&hash) {
-                Err(BankError::WalletDuplicate)?;
+                Err(BankError::WalletDuplicate { wallet: hash })?;
+            }
+
+            let pubkeys = wallet.multisig.as_ref().map_or(std::slice::from_ref(&wallet.pubkey), |m| m.pubkeys.as_slice());
+            for pubkey in pubkeys {
+                wallet.scheme.check_public_key(pubkey)?;
+            }
+
+            if let Some((currency, amount)) = grant {
+                self.check_issuance_cap(currency, amount)?;
+                wallet.add(currency, amount);
+                self.auditor.issue(currency, amount);
             }
 
-            let pubkey: Vec<u8> = data.pubkey.clone().try_into()?;
-            let mut wallet = Wallet::new(hash, pubkey);
-            wallet.add(1, 100.0);
             self.wallets.insert(hash, wallet);
             Ok(())
         } else {
             if !self.wallets.contains_key(&hash) {
-                Err(BankError::WalletNotFound)?;
+                Err(BankError::WalletNotFound { wallet: hash })?;
+            }
+
+            if let Some((currency, amount)) = grant {
+                self.auditor.retire(currency, amount);
             }
 
-            self.wallets.remove_entry(&hash).map_or(Err(BankError::WalletNotFound.into()), |_| Ok(()))
+            self.wallets.remove_entry(&hash).map_or(Err(BankError::WalletNotFound { wallet: hash }.into()), |_| Ok(()))
         }
     }
 
-    fn process_transaction_block(&mut self, block: &Block, data: &TransactionData, invert: bool) -> anyhow::Result<()>{
-        let from_hash: ByteHash = (&data.from).try_into()?;
-        let to_hash: ByteHash = (&data.to).try_into()?;
-
-        if !self.wallets.contains_key(&to_hash) {
-            return Err(BankError::WalletNotFound.into());
+    fn process_transaction_block(&mut self, block: &Block, data: &TransactionData, invert: bool) -> Result<()>{
+        if !data.amount.is_finite() || data.amount <= 0.0 {
+            Err(ValidationError::InvalidAmount { amount: data.amount })?;
         }
 
-        let from = self.wallets.get_mut(&from_hash).ok_or(BankError::WalletNotFound)?;
+        self.check_network(&[data.from, data.to])?;
+        let from_hash = data.from.hash();
+        let to_hash = data.to.hash();
+        self.get_currency(data.currency)?;
 
-        let data_vec: Vec<u8> = (&block.data).into();
-        let signature_bytes = hex::decode(&block.signature).unwrap();
-        let public_key = PKey::public_key_from_pem(&from.pubkey).unwrap();
-
-        if !rsc_crypto::signature::check(&public_key, data_vec.as_slice(), &signature_bytes)? {
-            Err(BankError::SignatureInvalid)?;
+        if !self.wallets.contains_key(&to_hash) {
+            return Err(BankError::WalletNotFound { wallet: to_hash }.into());
         }
 
+        self.check_signature(block, &from_hash)?;
+        self.check_recovered_sender(block, &from_hash)?;
         if !invert {
             {
-                let from_mut = self.wallets.get_mut(&from_hash).ok_or(BankError::WalletNotFound)?;
+                let from_mut = self.wallets.get_mut(&from_hash).ok_or(BankError::WalletNotFound { wallet: from_hash })?;
                 from_mut.deduct(data.currency, data.amount)?;
             }
 
             {
                 let to_mut = self.wallets.get_mut(&to_hash).expect("precheck");
-                to_mut.add(data.currency, data.amount);
+                match data.lock {
+                    Some(lock) => to_mut.add_locked(LockedAmount { currency: data.currency, amount: data.amount, lock }),
+                    None => to_mut.add(data.currency, data.amount),
+                }
             }
         } else {
             {
                 let to_mut = self.wallets.get_mut(&to_hash).expect("precheck");
-                to_mut.deduct(data.currency, data.amount)?;
+                match data.lock {
+                    Some(lock) => to_mut.remove_locked(&LockedAmount { currency: data.currency, amount: data.amount, lock })?,
+                    None => to_mut.deduct(data.currency, data.amount)?,
+                }
             }
 
             {
-                let from_mut = self.wallets.get_mut(&from_hash).ok_or(BankError::WalletNotFound)?;
+                let from_mut = self.wallets.get_mut(&from_hash).ok_or(BankError::WalletNotFound { wallet: from_hash })?;
                 from_mut.add(data.currency, data.amount);
             }
         }
 
         Ok(())
     }
+
+    fn process_batch_transfer_block(&mut self, block: &Block, data: &BatchTransferData, invert: bool) -> Result<()> {
+        let from_hash: ByteHash = (&data.from).try_into()?;
+
+        if data.legs.is_empty() {
+            Err(ValidationError::BatchEmpty)?;
+        }
+
+        let mut legs = Vec::<(ByteHash, u64, f64)>::with_capacity(data.legs.len());
+        for leg in &data.legs {
+            let to_hash: ByteHash = (&leg.to).try_into()?;
+            self.get_currency(leg.currency)?;
+
+            if !leg.amount.is_finite() || leg.amount <= 0.0 {
+                Err(ValidationError::InvalidAmount { amount: leg.amount })?;
+            }
+
+            if !self.wallets.contains_key(&to_hash) {
+                Err(BankError::WalletNotFound { wallet: to_hash })?;
+            }
+
+            legs.push((to_hash, leg.currency, leg.amount));
+        }
+
+        self.check_signature(block, &from_hash)?;
+
+        // Every leg is checked against the balance it will be paid from before anything is moved.
+        let mut totals = BTreeMap::<(ByteHash, u64), f64>::new();
+        for (to_hash, currency, amount) in &legs {
+            let payer = if invert { *to_hash } else { from_hash };
+            *totals.entry((payer, *currency)).or_default() += amount;
+        }
+
+        for ((payer, currency), total) in &totals {
+            let wallet = self.wallets.get(payer).expect("precheck");
+            let available = wallet.accounts.get(currency).copied().unwrap_or_default();
+            if available < *total {
+                Err(BankError::InsufficientCurrency { wallet: *payer, currency: *currency, required: *total, available })?;
+            }
+        }
+
+        for ((payer, currency), total) in totals {
+            self.wallets.get_mut(&payer).expect("precheck").deduct(currency, total)?;
+        }
+
+        for (to_hash, currency, amount) in legs {
+            let payee = if invert { from_hash } else { to_hash };
+            self.wallets.get_mut(&payee).expect("precheck").add(currency, amount);
+        }
+
+        Ok(())
+    }
+
+    fn process_approve_block(&mut self, block: &Block, data: &ApproveData, invert: bool) -> Result<()> {
+        let owner_hash: ByteHash = (&data.owner).try_into()?;
+        let spender_hash: ByteHash = (&data.spender).try_into()?;
+        self.get_currency(data.currency)?;
+
+        if !data.amount.is_finite() || data.amount < 0.0 {
+            Err(ValidationError::InvalidAmount { amount: data.amount })?;
+        }
+
+        if !self.wallets.contains_key(&spender_hash) {
+            Err(BankError::WalletNotFound { wallet: spender_hash })?;
+        }
+
+        self.check_signature(block, &owner_hash)?;
+
+        let key = (owner_hash, spender_hash, data.currency);
+        if !invert {
+            self.allowances.entry(key).or_default().push(data.amount);
+        } else {
+            let allowances = self.allowances.get_mut(&key).ok_or(BankError::AllowanceNotFound { owner: owner_hash, spender: spender_hash, currency: data.currency })?;
+            allowances.pop().ok_or(BankError::AllowanceNotFound { owner: owner_hash, spender: spender_hash, currency: data.currency })?;
+
+            if allowances.is_empty() {
+                self.allowances.remove(&key);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn process_transfer_from_block(&mut self, block: &Block, data: &TransferFromData, invert: bool) -> Result<()> {
+        let owner_hash: ByteHash = (&data.owner).try_into()?;
+        let spender_hash: ByteHash = (&data.spender).try_into()?;
+        let to_hash: ByteHash = (&data.to).try_into()?;
+        self.get_currency(data.currency)?;
+
+        if !data.amount.is_finite() || data.amount <= 0.0 {
+            Err(ValidationError::InvalidAmount { amount: data.amount })?;
+        }
+
+        for hash in [owner_hash, to_hash] {
+            if !self.wallets.contains_key(&hash) {
+                Err(BankError::WalletNotFound { wallet: hash })?;
+            }
+        }
+
+        self.check_signature(block, &spender_hash)?;
+
+        let allowance = self.allowances
+            .get_mut(&(owner_hash, spender_hash, data.currency))
+            .and_then(|a| a.last_mut())
+            .ok_or(BankError::AllowanceNotFound { owner: owner_hash, spender: spender_hash, currency: data.currency })?;
+
+        if !invert {
+            if data.amount > *allowance {
+                Err(BankError::AllowanceExceeded {
+                    owner: owner_hash,
+                    spender: spender_hash,
+                    currency: data.currency,
+                    required: data.amount,
+                    available: *allowance,
+                })?;
+            }
+
+            self.wallets.get_mut(&owner_hash).expect("precheck").deduct(data.currency, data.amount)?;
+            self.wallets.get_mut(&to_hash).expect("precheck").add(data.currency, data.amount);
+            *allowance -= data.amount;
+        } else {
+            self.wallets.get_mut(&to_hash).expect("precheck").deduct(data.currency, data.amount)?;
+            self.wallets.get_mut(&owner_hash).expect("precheck").add(data.currency, data.amount);
+            *allowance += data.amount;
+        }
+
+        Ok(())
+    }
+
+    fn process_issue_currency_block(&mut self, block: &Block, data: &IssueCurrencyData, invert: bool) -> Result<()> {
+        let issuer_hash: ByteHash = (&data.issuer).try_into()?;
+        self.check_signature(block, &issuer_hash)?;
+
+        if !data.supply.is_finite() || data.supply < 0.0 {
+            Err(ValidationError::InvalidAmount { amount: data.supply })?;
+        }
+
+        if !invert {
+            if self.currencies.contains_key(&data.currency) {
+                Err(BankError::CurrencyDuplicate { currency: data.currency })?;
+            }
+
+            self.check_issuance_cap(data.currency, data.supply)?;
+
+            let issuer = self.wallets.get_mut(&issuer_hash).expect("precheck");
+            issuer.add(data.currency, data.supply);
+            self.auditor.issue(data.currency, data.supply);
+
+            self.currencies.insert(data.currency, Currency {
+                id: data.currency,
+                symbol: data.symbol.clone(),
+                decimals: data.decimals,
+                issuer: Some(issuer_hash),
+                policy: data.policy,
+            });
+        } else {
+            if !self.currencies.contains_key(&data.currency) {
+                Err(BankError::CurrencyNotFound { currency: data.currency })?;
+            }
+
+            let issuer = self.wallets.get_mut(&issuer_hash).expect("precheck");
+            issuer.deduct(data.currency, data.supply)?;
+            self.auditor.retire(data.currency, data.supply);
+            self.currencies.remove(&data.currency);
+        }
+
+        Ok(())
+    }
+
+    fn process_mint_block(&mut self, block: &Block, data: &MintData, invert: bool) -> Result<()> {
+        let to_hash: ByteHash = (&data.to).try_into()?;
+        let currency = self.get_currency(data.currency)?;
+        let issuer_hash = currency.issuer.ok_or(BankError::CurrencyNoIssuer { currency: data.currency })?;
+
+        if currency.policy != SupplyPolicy::Mintable {
+            Err(BankError::CurrencyNotMintable { currency: data.currency })?;
+        }
+
+        if !data.amount.is_finite() || data.amount <= 0.0 {
+            Err(ValidationError::InvalidAmount { amount: data.amount })?;
+        }
+
+        self.check_signature(block, &issuer_hash)?;
+
+        if !invert {
+            self.check_issuance_cap(data.currency, data.amount)?;
+        }
+
+        let to = self.wallets.get_mut(&to_hash).ok_or(BankError::WalletNotFound { wallet: to_hash })?;
+        if !invert {
+            to.add(data.currency, data.amount);
+            self.auditor.issue(data.currency, data.amount);
+        } else {
+            to.deduct(data.currency, data.amount)?;
+            self.auditor.retire(data.currency, data.amount);
+        }
+
+        Ok(())
+    }
+
+    fn process_burn_block(&mut self, block: &Block, data: &BurnData, invert: bool) -> Result<()> {
+        let currency = self.get_currency(data.currency)?;
+        let issuer_hash = currency.issuer.ok_or(BankError::CurrencyNoIssuer { currency: data.currency })?;
+
+        if !data.amount.is_finite() || data.amount <= 0.0 {
+            Err(ValidationError::InvalidAmount { amount: data.amount })?;
+        }
+
+        self.check_signature(block, &issuer_hash)?;
+
+        let issuer = self.wallets.get_mut(&issuer_hash).ok_or(BankError::WalletNotFound { wallet: issuer_hash })?;
+        if !invert {
+            issuer.deduct(data.currency, data.amount)?;
+            self.auditor.retire(data.currency, data.amount);
+        } else {
+            issuer.add(data.currency, data.amount);
+            self.auditor.issue(data.currency, data.amount);
+        }
+
+        Ok(())
+    }
+
+    fn process_stake_block(&mut self, block: &Block, data: &StakeData, invert: bool) -> Result<()> {
+        let hash: ByteHash = (&data.wallet).try_into()?;
+
+        if !data.amount.is_finite() || data.amount <= 0.0 {
+            Err(ValidationError::InvalidAmount { amount: data.amount })?;
+        }
+
+        self.check_signature(block, &hash)?;
+
+        let wallet = self.wallets.get_mut(&hash).expect("precheck");
+        if !invert {
+            wallet.deduct(NATIVE_CURRENCY, data.amount)?;
+            self.stakes.bond(hash, data.amount, self.height);
+        } else {
+            self.stakes.revert(&hash)?;
+            wallet.add(NATIVE_CURRENCY, data.amount);
+        }
+
+        Ok(())
+    }
+
+    fn process_unstake_block(&mut self, block: &Block, data: &UnstakeData, invert: bool) -> Result<()> {
+        let hash: ByteHash = (&data.wallet).try_into()?;
+
+        if !data.amount.is_finite() || data.amount <= 0.0 {
+            Err(ValidationError::InvalidAmount { amount: data.amount })?;
+        }
+
+        self.check_signature(block, &hash)?;
+
+        let unbonding = LockedAmount {
+            currency: NATIVE_CURRENCY,
+            amount: data.amount,
+            lock: Lock::Height(self.height + UNBONDING_PERIOD),
+        };
+
+        let wallet = self.wallets.get_mut(&hash).expect("precheck");
+        if !invert {
+            self.stakes.unbond(hash, data.amount, self.height)?;
+            wallet.add_locked(unbonding);
+        } else {
+            wallet.remove_locked(&unbonding)?;
+            self.stakes.revert(&hash)?;
+        }
+
+        Ok(())
+    }
+
+    fn process_htlc_lock_block(&mut self, block: &Block, data: &HtlcLockData, invert: bool) -> Result<()> {
+        let id = data.hash_with(self.hasher);
+        let from_hash: ByteHash = (&data.from).try_into()?;
+        let to_hash: ByteHash = (&data.to).try_into()?;
+        let hashlock: ByteHash = (&data.hashlock).try_into()?;
+        self.get_currency(data.currency)?;
+
+        if !data.amount.is_finite() || data.amount <= 0.0 {
+            Err(ValidationError::InvalidAmount { amount: data.amount })?;
+        }
+
+        if !self.wallets.contains_key(&to_hash) {
+            Err(BankError::WalletNotFound { wallet: to_hash })?;
+        }
+
+        self.check_signature(block, &from_hash)?;
+
+        let from = self.wallets.get_mut(&from_hash).expect("precheck");
+        if !invert {
+            if self.htlcs.contains_key(&id) {
+                Err(BankError::HtlcDuplicate { htlc: id })?;
+            }
+
+            from.deduct(data.currency, data.amount)?;
+            self.htlcs.insert(id, Htlc {
+                from: from_hash,
+                to: to_hash,
+                currency: data.currency,
+                amount: data.amount,
+                hashlock,
+                timeout: data.timeout,
+                state: HtlcState::Locked,
+            });
+        } else {
+            let htlc = self.htlcs.get(&id).ok_or(BankError::HtlcNotFound { htlc: id })?;
+            if htlc.state != HtlcState::Locked {
+                Err(BankError::HtlcSettled { htlc: id })?;
+            }
+
+            from.add(data.currency, data.amount);
+            self.htlcs.remove(&id);
+        }
+
+        Ok(())
+    }
+
+    fn process_htlc_claim_block(&mut self, block: &Block, data: &HtlcClaimData, invert: bool) -> Result<()> {
+        let id: ByteHash = (&data.htlc).try_into()?;
+        let preimage = hex::decode(&data.preimage)?;
+        let htlc = self.htlcs.get_mut(&id).ok_or(BankError::HtlcNotFound { htlc: id })?;
+
+        let preimage_hash: ByteHash = Sha256::digest(preimage).try_into()?;
+        if preimage_hash != htlc.hashlock {
+            Err(BankError::HtlcPreimageInvalid { htlc: id })?;
+        }
+
+        let to = self.wallets.get_mut(&htlc.to).ok_or(BankError::WalletNotFound { wallet: htlc.to })?;
+        if !invert {
+            if htlc.state != HtlcState::Locked {
+                Err(BankError::HtlcSettled { htlc: id })?;
+            }
+
+            if htlc.timeout.is_unlocked(self.height, block.timestamp) {
+                Err(BankError::HtlcExpired { htlc: id, height: self.height })?;
+            }
+
+            to.add(htlc.currency, htlc.amount);
+            htlc.state = HtlcState::Claimed;
+        } else {
+            if htlc.state != HtlcState::Claimed {
+                Err(BankError::HtlcSettled { htlc: id })?;
+            }
+
+            to.deduct(htlc.currency, htlc.amount)?;
+            htlc.state = HtlcState::Locked;
+        }
+
+        Ok(())
+    }
+
+    fn process_htlc_refund_block(&mut self, block: &Block, data: &HtlcRefundData, invert: bool) -> Result<()> {
+        let id: ByteHash = (&data.htlc).try_into()?;
+        let htlc = self.htlcs.get_mut(&id).ok_or(BankError::HtlcNotFound { htlc: id })?;
+
+        let from = self.wallets.get_mut(&htlc.from).ok_or(BankError::WalletNotFound { wallet: htlc.from })?;
+        if !invert {
+            if htlc.state != HtlcState::Locked {
+                Err(BankError::HtlcSettled { htlc: id })?;
+            }
+
+            if !htlc.timeout.is_unlocked(self.height, block.timestamp) {
+                Err(BankError::HtlcNotExpired { htlc: id, height: self.height })?;
+            }
+
+            from.add(htlc.currency, htlc.amount);
+            htlc.state = HtlcState::Refunded;
+        } else {
+            if htlc.state != HtlcState::Refunded {
+                Err(BankError::HtlcSettled { htlc: id })?;
+            }
+
+            from.deduct(htlc.currency, htlc.amount)?;
+            htlc.state = HtlcState::Locked;
+        }
+
+        Ok(())
+    }
+
+    fn check_network(&self, addresses: &[Address]) -> Result<()> {
+        if let Some(address) = addresses.iter().find(|a| a.network() != self.network) {
+            Err(ValidationError::AddressNetworkMismatch { expected: self.network, found: address.network() })?;
+        }
+
+        Ok(())
+    }
+
+    /// Wallet whose signature the block data needs, if it can be told from the current state.
+    fn signer(&self, data: &BlockData) -> Option<ByteHash> {
+        let signer = match data {
+            BlockData::Transaction(data) => return Some(data.from.hash()),
+            BlockData::BatchTransfer(data) => &data.from,
+            BlockData::Approve(data) => &data.owner,
+            BlockData::TransferFrom(data) => &data.spender,
+            BlockData::RotateKey(data) => &data.wallet,
+            BlockData::IssueCurrency(data) => &data.issuer,
+            BlockData::Mint(MintData { currency, .. }) | BlockData::Burn(BurnData { currency, .. }) => {
+                return self.currencies.get(currency).and_then(|c| c.issuer);
+            },
+            BlockData::Stake(data) => &data.wallet,
+            BlockData::Unstake(data) => &data.wallet,
+            BlockData::HtlcLock(data) => &data.from,
+
+            _ => return None,
+        };
+
+        signer.try_into().ok()
+    }
+
+    /// Signature checks the block needs, against the keys currently registered.
+    ///
+    /// Multisig signatures are each checked against the one key they name.
+    fn signature_jobs(&self, block: &Block) -> Vec<SignatureJob> {
+        let Some(wallet) = self.signer(&block.data).and_then(|s| self.wallets.get(&s)) else {
+            return vec![];
+        };
+
+        let payload = SigningPayload::new(&self.chain_id).build(block.sequence, &block.data);
+        let job = |pubkey: &Vec<u8>, signature: Vec<u8>| SignatureJob { scheme: wallet.scheme, pubkey: pubkey.clone(), payload: payload.clone(), signature };
+
+        match &wallet.multisig {
+            None => hex::decode(&block.signature).map(|s| vec![job(&wallet.pubkey, s)]).unwrap_or_default(),
+            Some(multisig) => rsc_crypto::signature::decode_multi(&block.signature)
+                .unwrap_or_default()
+                .into_iter()
+                .filter_map(|(signer, s)| multisig.pubkeys.get(signer).map(|k| job(k, s)))
+                .collect(),
+        }
+    }
+
+    /// A signed block has to carry the signer's next sequence number, and be the signer's last one when undone.
+    fn check_sequence(&self, block: &Block, signer: &ByteHash, invert: bool) -> Result<()> {
+        let wallet = self.wallets.get(signer).ok_or(BankError::WalletNotFound { wallet: *signer })?;
+        let expected = if invert { wallet.sequence.checked_sub(1) } else { Some(wallet.sequence) };
+
+        if expected != Some(block.sequence) {
+            Err(BankError::SequenceMismatch { wallet: *signer, expected: expected.unwrap_or_default(), found: block.sequence })?;
+        }
+
+        Ok(())
+    }
+
+    fn check_signature(&self, block: &Block, signer: &ByteHash) -> Result<()> {
+        let wallet = self.wallets.get(signer).ok_or(BankError::WalletNotFound { wallet: *signer })?;
+
+        let payload = SigningPayload::new(&self.chain_id).build(block.sequence, &block.data);
+        if let Some(multisig) = &wallet.multisig {
+            return Self::check_multisig(&self.signatures, *signer, wallet.scheme, multisig, &payload, &block.signature);
+        }
+
+        Self::check_key_signature(&self.signatures, *signer, wallet.scheme, &wallet.pubkey, &payload, &block.signature)
+    }
+
+    /// For secp256k1 senders, the key recovered from the signature alone must be the sender's key.
+    fn check_recovered_sender(&self, block: &Block, sender: &ByteHash) -> Result<()> {
+        let wallet = self.wallets.get(sender).ok_or(BankError::WalletNotFound { wallet: *sender })?;
+        if wallet.scheme != Scheme::Secp256k1 || wallet.multisig.is_some() {
+            return Ok(());
+        }
+
+        let payload = SigningPayload::new(&self.chain_id).build(block.sequence, &block.data);
+        let recovered = Secp256k1::recover(&payload, &hex::decode(&block.signature)?)?;
+        if recovered != Secp256k1::normalize_public_key(&wallet.pubkey)? {
+            Err(BankError::SignatureInvalid { wallet: *sender })?;
+        }
+
+        Ok(())
+    }
+
+    fn check_key_signature(cache: &SignatureCache, wallet: ByteHash, scheme: Scheme, pubkey: &[u8], data: &[u8], signature: &str) -> Result<()> {
+        if !cache.check(scheme, pubkey, data, signature)? {
+            Err(BankError::SignatureInvalid { wallet })?;
+        }
+
+        Ok(())
+    }
+
+    fn check_multisig(cache: &SignatureCache, wallet: ByteHash, scheme: Scheme, multisig: &Multisig, data: &[u8], signature: &str) -> Result<()> {
+        // Keys were checked when the wallet was registered.
+        let public_keys = &multisig.pubkeys;
+        let mut signed_by = vec![false; public_keys.len()];
+        for (signer, signature_bytes) in rsc_crypto::signature::decode_multi(signature)? {
+            let public_key = public_keys.get(signer).ok_or(BankError::SignerUnknown { wallet })?;
+            if signed_by[signer] {
+                Err(BankError::SignerDuplicate { wallet })?;
+            }
+
+            if !cache.verify(scheme, public_key, data, &signature_bytes).unwrap_or(false) {
+                Err(BankError::SignatureInvalid { wallet })?;
+            }
+
+            signed_by[signer] = true;
+        }
+
+        let available = signed_by.into_iter().filter(|s| *s).count();
+        if available < multisig.threshold {
+            Err(BankError::SignatureThreshold { wallet, required: multisig.threshold, available })?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Ledger for Bank {
+    const MODEL: LedgerModel = LedgerModel::Account;
+
+    fn prevalidate(&self, blocks: &[&Block]) {
+        let jobs: Vec<SignatureJob> = blocks.iter().flat_map(|b| self.signature_jobs(b)).collect();
+        self.signatures.verify_batch(&jobs);
+    }
+
+    fn do_block(&mut self, block: &Block) -> Result<()> {
+        // Undoing a genesis block keeps the configuration it applied, so a failed one has to restore it.
+        let config = (self.policy.clone(), self.chain_id.clone(), self.network, self.hasher);
+
+        self.process_block(block, false)?;
+        if let Err(e) = self.process_subsidy(block, false) {
+            self.process_block(block, true).expect("undo of applied block");
+            (self.policy, self.chain_id, self.network, self.hasher) = config;
+            return Err(e);
+        }
+
+        self.release_locked(block);
+        self.height += 1;
+
+        self.auditor.check(|| self.holdings());
+        Ok(())
+    }
+
+    fn undo_block(&mut self, block: &Block) -> Result<()> {
+        self.height = self.height.checked_sub(1).expect("undo past genesis");
+        self.restore_locked()?;
+        self.process_subsidy(block, true)?;
+        self.process_block(block, true)?;
+
+        self.auditor.check(|| self.holdings());
+        Ok(())
+    }
+
+    fn movements(&self, block: &Block) -> Vec<Movement> {
+        let hash = |s: &String| -> Option<ByteHash> { s.try_into().ok() };
+        let grant = |wallet: ByteHash| -> Vec<Movement> {
+            self.policy
+                .wallet_grant(&wallet)
+                .map(|(currency, amount)| Movement::incoming(wallet, None, currency, amount))
+                .into_iter()
+                .collect()
+        };
+
+        let mut movements = match &block.data {
+            BlockData::Wallet(data) => grant(data.hash_with(self.hasher)),
+            BlockData::MultisigWallet(data) => grant(data.hash_with(self.hasher)),
+            BlockData::Transaction(data) => {
+                let (from, to) = (data.from.hash(), data.to.hash());
+                vec![
+                    Movement::outgoing(from, Some(to), data.currency, data.amount),
+                    Movement::incoming(to, Some(from), data.currency, data.amount),
+                ]
+            },
+            BlockData::BatchTransfer(data) => {
+                let Some(from) = hash(&data.from) else {
+                    return vec![];
+                };
+
+                let mut movements = Vec::<Movement>::new();
+                for leg in &data.legs {
+                    if let Some(to) = hash(&leg.to) {
+                        movements.push(Movement::outgoing(from, Some(to), leg.currency, leg.amount));
+                        movements.push(Movement::incoming(to, Some(from), leg.currency, leg.amount));
+                    }
+                }
+
+                movements
+            },
+            BlockData::Approve(data) => match (hash(&data.owner), hash(&data.spender)) {
+                (Some(owner), Some(spender)) => vec![
+                    Movement::approval(owner, spender, data.currency, data.amount),
+                    Movement::approval(spender, owner, data.currency, data.amount),
+                ],
+                _ => vec![],
+            },
+            BlockData::TransferFrom(data) => match (hash(&data.owner), hash(&data.to)) {
+                (Some(owner), Some(to)) => vec![
+                    Movement::outgoing(owner, Some(to), data.currency, data.amount),
+                    Movement::incoming(to, Some(owner), data.currency, data.amount),
+                ],
+                _ => vec![],
+            },
+            BlockData::IssueCurrency(data) => hash(&data.issuer)
+                .filter(|_| data.supply > 0.0)
+                .map(|issuer| Movement::incoming(issuer, None, data.currency, data.supply))
+                .into_iter()
+                .collect(),
+            BlockData::Mint(data) => hash(&data.to)
+                .map(|to| Movement::incoming(to, None, data.currency, data.amount))
+                .into_iter()
+                .collect(),
+            BlockData::Burn(data) => self.currencies
+                .get(&data.currency)
+                .and_then(|c| c.issuer)
+                .map(|issuer| Movement::outgoing(issuer, None, data.currency, data.amount))
+                .into_iter()
+                .collect(),
+            BlockData::HtlcLock(data) => match (hash(&data.from), hash(&data.to)) {
+                (Some(from), Some(to)) => vec![Movement::outgoing(from, Some(to), data.currency, data.amount)],
+                _ => vec![],
+            },
+            BlockData::HtlcClaim(data) => hash(&data.htlc)
+                .and_then(|id| self.htlcs.get(&id))
+                .map(|h| Movement::incoming(h.to, Some(h.from), h.currency, h.amount))
+                .into_iter()
+                .collect(),
+            BlockData::HtlcRefund(data) => hash(&data.htlc)
+                .and_then(|id| self.htlcs.get(&id))
+                .map(|h| Movement::incoming(h.from, Some(h.to), h.currency, h.amount))
+                .into_iter()
+                .collect(),
+
+            _ => vec![],
+        };
+
+        if let (Some(coinbase), Some((currency, amount))) = (block.coinbase, self.policy.block_subsidy(self.height)) {
+            movements.push(Movement::incoming(coinbase, None, currency, amount));
+        }
+
+        movements
+    }
 }
 
 impl Display for Wallet {
@@ -153,6 +1251,10 @@ impl Display for Wallet {
             f.write_fmt(format_args!("{}={},", c, a))?;
         }
 
+        for l in &self.locked {
+            f.write_fmt(format_args!("{}={}@{},", l.currency, l.amount, l.lock))?;
+        }
+
         Ok(())
     }
 }
@@ -0,0 +1,123 @@
+use std::sync::Arc;
+
+use crate::{rsc_util::hash::ByteHash, rsc_blockdata::block_data::MonetaryPolicyConfig, rsc_error::{Result, ValidationError}};
+
+use super::{WALLET_GRANT, currency::NATIVE_CURRENCY};
+
+/// Decides how much new money enters circulation, and where.
+pub trait MonetaryPolicy: Send + Sync {
+    /// Currency and amount credited to a newly registered wallet.
+    fn wallet_grant(&self, wallet: &ByteHash) -> Option<(u64, f64)>;
+
+    /// Currency and amount credited to the coinbase wallet of the block at given height.
+    fn block_subsidy(&self, height: u64) -> Option<(u64, f64)>;
+
+    /// Upper bound on total supply of the currency.
+    fn issuance_cap(&self, currency: u64) -> Option<f64>;
+}
+
+/// Every new wallet is granted a fixed amount of native currency.
+pub struct FaucetPolicy {
+    pub grant: f64,
+    pub subsidy: f64,
+}
+
+impl Default for FaucetPolicy {
+    fn default() -> Self {
+        FaucetPolicy { grant: WALLET_GRANT, subsidy: 0.0 }
+    }
+}
+
+impl MonetaryPolicy for FaucetPolicy {
+    fn wallet_grant(&self, _wallet: &ByteHash) -> Option<(u64, f64)> {
+        Some((NATIVE_CURRENCY, self.grant)).filter(|(_, a)| *a > 0.0)
+    }
+
+    fn block_subsidy(&self, _height: u64) -> Option<(u64, f64)> {
+        Some((NATIVE_CURRENCY, self.subsidy)).filter(|(_, a)| *a > 0.0)
+    }
+
+    fn issuance_cap(&self, _currency: u64) -> Option<f64> {
+        None
+    }
+}
+
+/// Native currency only enters circulation through block subsidies.
+pub struct NoGrantPolicy {
+    pub subsidy: f64,
+}
+
+impl MonetaryPolicy for NoGrantPolicy {
+    fn wallet_grant(&self, _wallet: &ByteHash) -> Option<(u64, f64)> {
+        None
+    }
+
+    fn block_subsidy(&self, _height: u64) -> Option<(u64, f64)> {
+        Some((NATIVE_CURRENCY, self.subsidy)).filter(|(_, a)| *a > 0.0)
+    }
+
+    fn issuance_cap(&self, _currency: u64) -> Option<f64> {
+        None
+    }
+}
+
+/// Whole native supply is granted to a single wallet once it's registered, nothing is issued afterwards.
+pub struct PreminePolicy {
+    pub wallet: ByteHash,
+    pub amount: f64,
+}
+
+impl MonetaryPolicy for PreminePolicy {
+    fn wallet_grant(&self, wallet: &ByteHash) -> Option<(u64, f64)> {
+        Some((NATIVE_CURRENCY, self.amount)).filter(|_| *wallet == self.wallet)
+    }
+
+    fn block_subsidy(&self, _height: u64) -> Option<(u64, f64)> {
+        None
+    }
+
+    fn issuance_cap(&self, currency: u64) -> Option<f64> {
+        Some(self.amount).filter(|_| currency == NATIVE_CURRENCY)
+    }
+}
+
+/// Policy described by the genesis configuration, rejecting amounts that aren't finite and non-negative.
+pub fn from_config(config: &MonetaryPolicyConfig) -> Result<Arc<dyn MonetaryPolicy>> {
+    let amounts = match config {
+        MonetaryPolicyConfig::Faucet { grant, subsidy } => vec![*grant, *subsidy],
+        MonetaryPolicyConfig::NoGrant { subsidy } => vec![*subsidy],
+        MonetaryPolicyConfig::Premine { amount, .. } => vec![*amount],
+    };
+
+    if let Some(amount) = amounts.into_iter().find(|a| !a.is_finite() || *a < 0.0) {
+        Err(ValidationError::InvalidAmount { amount })?;
+    }
+
+    Ok(match config {
+        MonetaryPolicyConfig::Faucet { grant, subsidy } => Arc::new(FaucetPolicy { grant: *grant, subsidy: *subsidy }),
+        MonetaryPolicyConfig::NoGrant { subsidy } => Arc::new(NoGrantPolicy { subsidy: *subsidy }),
+        MonetaryPolicyConfig::Premine { wallet, amount } => Arc::new(PreminePolicy { wallet: wallet.try_into()?, amount: *amount }),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rsc_error::ErrorCode;
+
+    #[test]
+    fn rejects_invalid_amounts() {
+        for amount in [-1.0, f64::NAN, f64::INFINITY] {
+            for config in [
+                MonetaryPolicyConfig::Faucet { grant: amount, subsidy: 0.0 },
+                MonetaryPolicyConfig::Faucet { grant: 0.0, subsidy: amount },
+                MonetaryPolicyConfig::NoGrant { subsidy: amount },
+                MonetaryPolicyConfig::Premine { wallet: ByteHash::new().to_string(), amount },
+            ] {
+                assert_eq!(from_config(&config).err().map(|e| e.code()), Some(1003), "{:?}", config);
+            }
+        }
+
+        assert!(from_config(&MonetaryPolicyConfig::Faucet { grant: 0.0, subsidy: 0.0 }).is_ok());
+    }
+}
@@ -0,0 +1,72 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::rsc_util::hash::ByteHash;
+
+use super::{BankError, serialize_entries};
+
+/// Number of blocks unstaked amounts stay locked before becoming spendable.
+pub const UNBONDING_PERIOD: u64 = 10;
+
+/// Native currency bonded by wallets, along with the total stake of each wallet over height.
+#[derive(Clone, Default, Serialize)]
+pub struct StakeRegistry {
+    #[serde(serialize_with = "serialize_entries")]
+    bonded: BTreeMap<ByteHash, f64>,
+    #[serde(serialize_with = "serialize_entries")]
+    history: BTreeMap<ByteHash, Vec<(u64, f64)>>,
+}
+
+impl StakeRegistry {
+    pub fn stake(&self, wallet: &ByteHash) -> f64 {
+        self.bonded.get(wallet).copied().unwrap_or_default()
+    }
+
+    /// Total stake of the wallet after the block at given height was applied.
+    pub fn stake_at(&self, wallet: &ByteHash, height: u64) -> f64 {
+        self.history
+            .get(wallet)
+            .and_then(|h| h.iter().rev().find(|(h, _)| *h <= height))
+            .map_or(0.0, |(_, total)| *total)
+    }
+
+    pub fn total(&self) -> f64 {
+        self.bonded.values().sum()
+    }
+
+    pub fn bond(&mut self, wallet: ByteHash, amount: f64, height: u64) {
+        let total = self.bonded.entry(wallet).or_default();
+        *total += amount;
+        self.history.entry(wallet).or_default().push((height, *total));
+    }
+
+    pub fn unbond(&mut self, wallet: ByteHash, amount: f64, height: u64) -> Result<(), BankError> {
+        let available = self.stake(&wallet);
+        if available < amount {
+            return Err(BankError::InsufficientStake { wallet, required: amount, available });
+        }
+
+        let total = self.bonded.get_mut(&wallet).expect("precheck");
+        *total -= amount;
+        self.history.entry(wallet).or_default().push((height, *total));
+        Ok(())
+    }
+
+    /// Drops the most recent change to the wallet's stake, restoring the previous total.
+    pub fn revert(&mut self, wallet: &ByteHash) -> Result<(), BankError> {
+        let mismatch = || BankError::StakeHistoryMismatch { wallet: *wallet };
+        let history = self.history.get_mut(wallet).ok_or_else(mismatch)?;
+        history.pop().ok_or_else(mismatch)?;
+
+        match history.last() {
+            Some((_, total)) => { self.bonded.insert(*wallet, *total); },
+            None => {
+                self.history.remove(wallet);
+                self.bonded.remove(wallet);
+            },
+        }
+
+        Ok(())
+    }
+}
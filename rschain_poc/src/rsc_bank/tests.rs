@@ -0,0 +1,719 @@
+use sha2::{Digest, Sha256};
+
+use crate::rsc_blockdata::{BlockData, block_data::{LedgerModel, MonetaryPolicyConfig, Lock, HtlcLockData, HtlcClaimData, HtlcRefundData, BatchTransferData, TransferLeg, ApproveData, TransferFromData, StakeData, UnstakeData, TransactionData, WalletData, MultisigWalletData, RotateKeyData, IssueCurrencyData, MintData, BurnData, SupplyPolicy}};
+use crate::rsc_core::block::Block;
+use crate::rsc_crypto::{keystore::WalletKey, payload::SigningPayload, signature::{self, Scheme}};
+use crate::rsc_error::ErrorCode;
+use crate::rsc_testing::{self, TestWallet};
+use crate::rsc_util::{address::Network, hash::{ByteHash, Hashable}, hasher::HashAlgorithm};
+
+use super::{Bank, WALLET_GRANT, currency::NATIVE_CURRENCY, htlc::HtlcState, ledger::Ledger, policy::MonetaryPolicy, stake::UNBONDING_PERIOD};
+
+/// Bank past its genesis block with the wallets registered, along with the hash of the last block.
+fn bank_with(wallets: &[&TestWallet]) -> (Bank, ByteHash) {
+    let mut bank = Bank::new();
+    let genesis = rsc_testing::genesis(LedgerModel::Account, None);
+    bank.do_block(&genesis).unwrap();
+
+    let mut tip = genesis.hash;
+    for wallet in wallets {
+        let block = wallet.register(tip);
+        bank.do_block(&block).unwrap();
+        tip = block.hash;
+    }
+
+    (bank, tip)
+}
+
+/// Applies the block, checks undoing it restores the exact state it was applied to, then applies it again.
+fn round_trip(bank: &mut Bank, block: &Block) {
+    let before = String::from_utf8(bank.serialize_state()).unwrap();
+    bank.do_block(block).unwrap();
+    let after = bank.serialize_state();
+
+    bank.undo_block(block).unwrap();
+    assert_eq!(String::from_utf8(bank.serialize_state()).unwrap(), before);
+
+    bank.do_block(block).unwrap();
+    assert_eq!(bank.serialize_state(), after);
+}
+
+fn balance(bank: &Bank, wallet: &TestWallet) -> f64 {
+    bank.wallets[&wallet.hash].accounts.get(&NATIVE_CURRENCY).copied().unwrap_or_default()
+}
+
+fn locked(bank: &Bank, wallet: &TestWallet) -> f64 {
+    bank.wallets[&wallet.hash].locked.iter().map(|l| l.amount).sum()
+}
+
+#[test]
+fn transfer_round_trip() {
+    let (alice, bob) = (TestWallet::new(), TestWallet::new());
+    let (mut bank, tip) = bank_with(&[&alice, &bob]);
+
+    round_trip(&mut bank, &alice.signed(tip, 0, alice.transfer(&bob, 30.0, None)));
+    assert_eq!(balance(&bank, &alice), WALLET_GRANT - 30.0);
+    assert_eq!(balance(&bank, &bob), WALLET_GRANT + 30.0);
+}
+
+#[test]
+fn rejects_invalid_transfer_amounts() {
+    let (alice, bob) = (TestWallet::new(), TestWallet::new());
+    let (mut bank, tip) = bank_with(&[&alice, &bob]);
+    let before = bank.serialize_state();
+
+    for amount in [-50.0, 0.0, f64::NAN, f64::INFINITY] {
+        for lock in [None, Some(Lock::Height(10))] {
+            let error = bank.do_block(&alice.signed(tip, 0, alice.transfer(&bob, amount, lock))).unwrap_err();
+            assert_eq!(error.code(), 1003, "{}", amount);
+        }
+    }
+
+    assert_eq!(bank.serialize_state(), before);
+}
+
+#[test]
+fn height_lock_releases_at_height_and_relocks_on_undo() {
+    let (alice, bob) = (TestWallet::new(), TestWallet::new());
+    let (mut bank, tip) = bank_with(&[&alice, &bob]);
+    let unlock_height = bank.height + 2;
+
+    let transfer = alice.signed(tip, 0, alice.transfer(&bob, 40.0, Some(Lock::Height(unlock_height))));
+    round_trip(&mut bank, &transfer);
+    assert_eq!((balance(&bank, &bob), locked(&bank, &bob)), (WALLET_GRANT, 40.0));
+
+    let before_unlock = rsc_testing::block(transfer.hash, BlockData::Empty);
+    round_trip(&mut bank, &before_unlock);
+    assert_eq!(locked(&bank, &bob), 40.0);
+
+    let at_unlock = rsc_testing::block(before_unlock.hash, BlockData::Empty);
+    assert_eq!(bank.height, unlock_height);
+    round_trip(&mut bank, &at_unlock);
+    assert_eq!((balance(&bank, &bob), locked(&bank, &bob)), (WALLET_GRANT + 40.0, 0.0));
+
+    bank.undo_block(&at_unlock).unwrap();
+    assert_eq!((balance(&bank, &bob), locked(&bank, &bob)), (WALLET_GRANT, 40.0));
+}
+
+#[test]
+fn time_lock_releases_by_block_timestamp() {
+    let (alice, bob) = (TestWallet::new(), TestWallet::new());
+    let (mut bank, tip) = bank_with(&[&alice, &bob]);
+    let unlock_time = crate::rsc_core::block::now() + 60;
+
+    let transfer = alice.signed(tip, 0, alice.transfer(&bob, 25.0, Some(Lock::Time(unlock_time))));
+    round_trip(&mut bank, &transfer);
+
+    let before_unlock = rsc_testing::block_at(transfer.hash, BlockData::Empty, unlock_time - 1);
+    round_trip(&mut bank, &before_unlock);
+    assert_eq!(locked(&bank, &bob), 25.0);
+
+    let at_unlock = rsc_testing::block_at(before_unlock.hash, BlockData::Empty, unlock_time);
+    round_trip(&mut bank, &at_unlock);
+    assert_eq!((balance(&bank, &bob), locked(&bank, &bob)), (WALLET_GRANT + 25.0, 0.0));
+
+    bank.undo_block(&at_unlock).unwrap();
+    assert_eq!((balance(&bank, &bob), locked(&bank, &bob)), (WALLET_GRANT, 25.0));
+}
+
+#[test]
+fn lock_released_by_its_own_block_round_trips() {
+    let (alice, bob) = (TestWallet::new(), TestWallet::new());
+    let (mut bank, tip) = bank_with(&[&alice, &bob]);
+
+    let transfer = alice.signed(tip, 0, alice.transfer(&bob, 10.0, Some(Lock::Height(bank.height))));
+    // Released by the transfer block itself, undo re-locks it before taking it back.
+    round_trip(&mut bank, &transfer);
+    assert_eq!((balance(&bank, &bob), locked(&bank, &bob)), (WALLET_GRANT + 10.0, 0.0));
+}
+
+const PREIMAGE: &[u8] = b"swap secret";
+
+/// HTLC from one wallet to another under the hash of `PREIMAGE`, along with its identifier.
+fn htlc(from: &TestWallet, to: &TestWallet, amount: f64, timeout: Lock) -> (BlockData, String) {
+    let data = HtlcLockData {
+        from: from.hash.to_string(),
+        to: to.hash.to_string(),
+        currency: NATIVE_CURRENCY,
+        amount,
+        hashlock: hex::encode(Sha256::digest(PREIMAGE)),
+        timeout,
+    };
+
+    let id = data.hash().to_string();
+    (BlockData::HtlcLock(data), id)
+}
+
+fn claim(htlc: &str, preimage: &[u8]) -> BlockData {
+    BlockData::HtlcClaim(HtlcClaimData { htlc: htlc.to_string(), preimage: hex::encode(preimage) })
+}
+
+fn refund(htlc: &str) -> BlockData {
+    BlockData::HtlcRefund(HtlcRefundData { htlc: htlc.to_string() })
+}
+
+#[test]
+fn htlc_claim_round_trip() {
+    let (alice, bob) = (TestWallet::new(), TestWallet::new());
+    let (mut bank, tip) = bank_with(&[&alice, &bob]);
+
+    let (data, id) = htlc(&alice, &bob, 30.0, Lock::Height(bank.height + 10));
+    let lock = alice.signed(tip, 0, data);
+    round_trip(&mut bank, &lock);
+    assert_eq!(balance(&bank, &alice), WALLET_GRANT - 30.0);
+
+    let wrong = rsc_testing::block(lock.hash, claim(&id, b"guess"));
+    assert_eq!(bank.do_block(&wrong).unwrap_err().code(), 2018);
+
+    let claimed = rsc_testing::block(lock.hash, claim(&id, PREIMAGE));
+    round_trip(&mut bank, &claimed);
+    assert_eq!(balance(&bank, &bob), WALLET_GRANT + 30.0);
+
+    let again = rsc_testing::block(claimed.hash, claim(&id, PREIMAGE));
+    assert_eq!(bank.do_block(&again).unwrap_err().code(), 2017);
+
+    // Undoing the claim puts the funds back in escrow, where undoing the lock returns them.
+    bank.undo_block(&claimed).unwrap();
+    assert!(bank.htlcs.values().all(|h| h.state == HtlcState::Locked));
+    bank.undo_block(&lock).unwrap();
+    assert!(bank.htlcs.is_empty());
+    assert_eq!((balance(&bank, &alice), balance(&bank, &bob)), (WALLET_GRANT, WALLET_GRANT));
+}
+
+#[test]
+fn htlc_refund_round_trip() {
+    let (alice, bob) = (TestWallet::new(), TestWallet::new());
+    let (mut bank, tip) = bank_with(&[&alice, &bob]);
+
+    let (data, id) = htlc(&alice, &bob, 30.0, Lock::Height(bank.height + 2));
+    let lock = alice.signed(tip, 0, data);
+    round_trip(&mut bank, &lock);
+
+    let early = rsc_testing::block(lock.hash, refund(&id));
+    assert_eq!(bank.do_block(&early).unwrap_err().code(), 2020);
+
+    let filler = rsc_testing::block(lock.hash, BlockData::Empty);
+    bank.do_block(&filler).unwrap();
+
+    let late_claim = rsc_testing::block(filler.hash, claim(&id, PREIMAGE));
+    assert_eq!(bank.do_block(&late_claim).unwrap_err().code(), 2019);
+
+    let refunded = rsc_testing::block(filler.hash, refund(&id));
+    round_trip(&mut bank, &refunded);
+    assert_eq!((balance(&bank, &alice), balance(&bank, &bob)), (WALLET_GRANT, WALLET_GRANT));
+    assert!(bank.htlcs.values().all(|h| h.state == HtlcState::Refunded));
+
+    bank.undo_block(&refunded).unwrap();
+    bank.undo_block(&filler).unwrap();
+    bank.undo_block(&lock).unwrap();
+    assert!(bank.htlcs.is_empty());
+    assert_eq!(balance(&bank, &alice), WALLET_GRANT);
+}
+
+#[test]
+fn htlc_time_timeout_uses_block_timestamp() {
+    let (alice, bob) = (TestWallet::new(), TestWallet::new());
+    let (mut bank, tip) = bank_with(&[&alice, &bob]);
+    let timeout = crate::rsc_core::block::now() + 60;
+
+    let (data, id) = htlc(&alice, &bob, 30.0, Lock::Time(timeout));
+    let lock = alice.signed(tip, 0, data);
+    bank.do_block(&lock).unwrap();
+
+    let early = rsc_testing::block_at(lock.hash, refund(&id), timeout - 1);
+    assert_eq!(bank.do_block(&early).unwrap_err().code(), 2020);
+
+    let refunded = rsc_testing::block_at(lock.hash, refund(&id), timeout);
+    round_trip(&mut bank, &refunded);
+    assert_eq!(balance(&bank, &alice), WALLET_GRANT);
+}
+
+fn batch(from: &TestWallet, legs: &[(&TestWallet, f64)]) -> BlockData {
+    BlockData::BatchTransfer(BatchTransferData {
+        from: from.hash.to_string(),
+        legs: legs.iter().map(|(to, amount)| TransferLeg { to: to.hash.to_string(), currency: NATIVE_CURRENCY, amount: *amount }).collect(),
+    })
+}
+
+#[test]
+fn batch_transfer_round_trip() {
+    let (alice, bob, carol) = (TestWallet::new(), TestWallet::new(), TestWallet::new());
+    let (mut bank, tip) = bank_with(&[&alice, &bob, &carol]);
+
+    round_trip(&mut bank, &alice.signed(tip, 0, batch(&alice, &[(&bob, 20.0), (&carol, 30.0), (&bob, 5.0)])));
+    assert_eq!(balance(&bank, &alice), WALLET_GRANT - 55.0);
+    assert_eq!((balance(&bank, &bob), balance(&bank, &carol)), (WALLET_GRANT + 25.0, WALLET_GRANT + 30.0));
+}
+
+#[test]
+fn batch_transfer_applies_all_legs_or_none() {
+    let (alice, bob, carol) = (TestWallet::new(), TestWallet::new(), TestWallet::new());
+    let (mut bank, tip) = bank_with(&[&alice, &bob, &carol]);
+    let before = bank.serialize_state();
+
+    // Each leg is covered on its own, both together are not.
+    let overdrawn = alice.signed(tip, 0, batch(&alice, &[(&bob, WALLET_GRANT), (&carol, 1.0)]));
+    assert_eq!(bank.do_block(&overdrawn).unwrap_err().code(), 2004);
+
+    let invalid = alice.signed(tip, 0, batch(&alice, &[(&bob, 1.0), (&carol, -1.0)]));
+    assert_eq!(bank.do_block(&invalid).unwrap_err().code(), 1003);
+
+    assert_eq!(bank.do_block(&alice.signed(tip, 0, batch(&alice, &[]))).unwrap_err().code(), 1006);
+    assert_eq!(bank.serialize_state(), before);
+}
+
+#[test]
+fn batch_transfer_undo_after_recipient_pays_on() {
+    let (alice, bob, carol) = (TestWallet::new(), TestWallet::new(), TestWallet::new());
+    let (mut bank, tip) = bank_with(&[&alice, &bob, &carol]);
+    let before = bank.serialize_state();
+
+    let first = alice.signed(tip, 0, batch(&alice, &[(&bob, 40.0), (&carol, 10.0)]));
+    bank.do_block(&first).unwrap();
+    let second = bob.signed(first.hash, 0, batch(&bob, &[(&carol, WALLET_GRANT + 40.0)]));
+    round_trip(&mut bank, &second);
+    assert_eq!(balance(&bank, &bob), 0.0);
+
+    bank.undo_block(&second).unwrap();
+    bank.undo_block(&first).unwrap();
+    assert_eq!(bank.serialize_state(), before);
+}
+
+fn approve(owner: &TestWallet, spender: &TestWallet, amount: f64) -> BlockData {
+    BlockData::Approve(ApproveData { owner: owner.hash.to_string(), spender: spender.hash.to_string(), currency: NATIVE_CURRENCY, amount })
+}
+
+fn transfer_from(owner: &TestWallet, spender: &TestWallet, to: &TestWallet, amount: f64) -> BlockData {
+    BlockData::TransferFrom(TransferFromData {
+        owner: owner.hash.to_string(),
+        spender: spender.hash.to_string(),
+        to: to.hash.to_string(),
+        currency: NATIVE_CURRENCY,
+        amount,
+    })
+}
+
+#[test]
+fn allowance_round_trip() {
+    let (alice, bob, carol) = (TestWallet::new(), TestWallet::new(), TestWallet::new());
+    let (mut bank, tip) = bank_with(&[&alice, &bob, &carol]);
+
+    let approved = alice.signed(tip, 0, approve(&alice, &bob, 50.0));
+    round_trip(&mut bank, &approved);
+    assert_eq!(bank.allowance(&alice.hash, &bob.hash, NATIVE_CURRENCY), 50.0);
+
+    let spent = bob.signed(approved.hash, 0, transfer_from(&alice, &bob, &carol, 30.0));
+    round_trip(&mut bank, &spent);
+    assert_eq!(bank.allowance(&alice.hash, &bob.hash, NATIVE_CURRENCY), 20.0);
+    assert_eq!((balance(&bank, &alice), balance(&bank, &carol)), (WALLET_GRANT - 30.0, WALLET_GRANT + 30.0));
+
+    let over = bob.signed(spent.hash, 1, transfer_from(&alice, &bob, &carol, 25.0));
+    assert_eq!(bank.do_block(&over).unwrap_err().code(), 2023);
+
+    let unsigned = carol.signed(spent.hash, 1, transfer_from(&alice, &bob, &carol, 5.0));
+    assert_eq!(bank.do_block(&unsigned).unwrap_err().code(), 2003);
+}
+
+#[test]
+fn replaced_allowance_restored_on_undo() {
+    let (alice, bob, carol) = (TestWallet::new(), TestWallet::new(), TestWallet::new());
+    let (mut bank, tip) = bank_with(&[&alice, &bob, &carol]);
+    let before = bank.serialize_state();
+
+    let first = alice.signed(tip, 0, approve(&alice, &bob, 50.0));
+    bank.do_block(&first).unwrap();
+    let spent = bob.signed(first.hash, 0, transfer_from(&alice, &bob, &carol, 10.0));
+    bank.do_block(&spent).unwrap();
+
+    // Replacing the allowance, also with zero to revoke it, keeps the one it replaced for undo.
+    let revoked = alice.signed(spent.hash, 1, approve(&alice, &bob, 0.0));
+    round_trip(&mut bank, &revoked);
+    assert_eq!(bank.allowance(&alice.hash, &bob.hash, NATIVE_CURRENCY), 0.0);
+
+    bank.undo_block(&revoked).unwrap();
+    assert_eq!(bank.allowance(&alice.hash, &bob.hash, NATIVE_CURRENCY), 40.0);
+
+    bank.undo_block(&spent).unwrap();
+    bank.undo_block(&first).unwrap();
+    assert_eq!(bank.serialize_state(), before);
+}
+
+fn stake(wallet: &TestWallet, amount: f64) -> BlockData {
+    BlockData::Stake(StakeData { wallet: wallet.hash.to_string(), amount })
+}
+
+fn unstake(wallet: &TestWallet, amount: f64) -> BlockData {
+    BlockData::Unstake(UnstakeData { wallet: wallet.hash.to_string(), amount })
+}
+
+#[test]
+fn stake_round_trip() {
+    let alice = TestWallet::new();
+    let (mut bank, tip) = bank_with(&[&alice]);
+    let height = bank.height;
+
+    let first = alice.signed(tip, 0, stake(&alice, 30.0));
+    round_trip(&mut bank, &first);
+    let second = alice.signed(first.hash, 1, stake(&alice, 20.0));
+    round_trip(&mut bank, &second);
+
+    assert_eq!(balance(&bank, &alice), WALLET_GRANT - 50.0);
+    assert_eq!(bank.stakes.stake(&alice.hash), 50.0);
+    assert_eq!((bank.stakes.stake_at(&alice.hash, height), bank.stakes.stake_at(&alice.hash, height + 1)), (30.0, 50.0));
+
+    let overdrawn = alice.signed(second.hash, 2, stake(&alice, WALLET_GRANT));
+    assert_eq!(bank.do_block(&overdrawn).unwrap_err().code(), 2004);
+
+    bank.undo_block(&second).unwrap();
+    bank.undo_block(&first).unwrap();
+    assert_eq!((balance(&bank, &alice), bank.stakes.stake(&alice.hash)), (WALLET_GRANT, 0.0));
+}
+
+#[test]
+fn unstake_unbonds_after_period_and_relocks_on_undo() {
+    let alice = TestWallet::new();
+    let (mut bank, tip) = bank_with(&[&alice]);
+
+    let staked = alice.signed(tip, 0, stake(&alice, 50.0));
+    bank.do_block(&staked).unwrap();
+
+    let over = alice.signed(staked.hash, 1, unstake(&alice, 60.0));
+    assert_eq!(bank.do_block(&over).unwrap_err().code(), 2024);
+
+    let unlock_height = bank.height + UNBONDING_PERIOD;
+    let unstaked = alice.signed(staked.hash, 1, unstake(&alice, 20.0));
+    round_trip(&mut bank, &unstaked);
+    assert_eq!((bank.stakes.stake(&alice.hash), locked(&bank, &alice)), (30.0, 20.0));
+
+    let mut tip = unstaked.hash;
+    while bank.height < unlock_height {
+        let filler = rsc_testing::block(tip, BlockData::Empty);
+        bank.do_block(&filler).unwrap();
+        tip = filler.hash;
+    }
+    assert_eq!(locked(&bank, &alice), 20.0);
+
+    let at_unlock = rsc_testing::block(tip, BlockData::Empty);
+    round_trip(&mut bank, &at_unlock);
+    assert_eq!((balance(&bank, &alice), locked(&bank, &alice)), (WALLET_GRANT - 30.0, 0.0));
+
+    bank.undo_block(&at_unlock).unwrap();
+    assert_eq!((balance(&bank, &alice), locked(&bank, &alice)), (WALLET_GRANT - 50.0, 20.0));
+}
+
+#[test]
+fn secp256k1_wallet_registered_with_any_key_encoding_can_send() {
+    let bob = TestWallet::new();
+    let (mut bank, mut tip) = bank_with(&[&bob]);
+
+    for compress in [true, false] {
+        let alice = TestWallet::with_scheme(Scheme::Secp256k1);
+        let WalletKey::Secp256k1(key) = &alice.key else { unreachable!() };
+        let pubkey = hex::encode_upper(key.verifying_key().to_encoded_point(compress).as_bytes());
+        let data = WalletData { pubkey, scheme: Scheme::Secp256k1 };
+        let from = data.hash();
+
+        let registered = rsc_testing::block(tip, BlockData::Wallet(data));
+        bank.do_block(&registered).unwrap();
+
+        let transfer = BlockData::Transaction(TransactionData {
+            from: from.to_address(rsc_testing::NETWORK),
+            to: bob.address(),
+            currency: NATIVE_CURRENCY,
+            amount: 10.0,
+            lock: None,
+        });
+        let sent = alice.signed(registered.hash, 0, transfer);
+        round_trip(&mut bank, &sent);
+        assert_eq!(bank.wallets[&from].accounts[&NATIVE_CURRENCY], WALLET_GRANT - 10.0);
+        tip = sent.hash;
+    }
+}
+
+#[test]
+fn rejects_wallets_with_invalid_public_keys() {
+    let alice = TestWallet::new();
+    let (mut bank, tip) = bank_with(&[]);
+    let before = bank.serialize_state();
+
+    for (pubkey, scheme) in [("00".repeat(31), Scheme::Ed25519), ("05".repeat(33), Scheme::Secp256k1), ("not a pem".to_string(), Scheme::Rsa)] {
+        let block = rsc_testing::block(tip, BlockData::Wallet(WalletData { pubkey, scheme }));
+        assert_eq!(bank.do_block(&block).unwrap_err().code(), 1004, "{:?}", scheme);
+    }
+
+    let multisig = MultisigWalletData { pubkeys: vec![alice.data().pubkey, "00".repeat(31)], threshold: 1, scheme: Scheme::Ed25519 };
+    let block = rsc_testing::block(tip, BlockData::MultisigWallet(multisig));
+    assert_eq!(bank.do_block(&block).unwrap_err().code(), 1004);
+
+    assert_eq!(bank.serialize_state(), before);
+}
+
+#[test]
+fn failed_genesis_block_leaves_configuration_unchanged() {
+    let mut bank = Bank::new();
+    let alice = TestWallet::new();
+
+    let mut data = rsc_testing::genesis_data(LedgerModel::Account, Some(MonetaryPolicyConfig::Faucet { grant: 1.0, subsidy: 5.0 }));
+    data.hasher = HashAlgorithm::Blake3;
+
+    // The subsidy goes to a wallet which does not exist yet.
+    let mut genesis = Block::new(ByteHash::new(), BlockData::Genesis(data));
+    genesis.coinbase = Some(alice.hash);
+    genesis.update_nonce(0, genesis.hash_algorithm(HashAlgorithm::default()));
+    assert_eq!(bank.do_block(&genesis).unwrap_err().code(), 2001);
+
+    assert_eq!((bank.height, bank.chain_id.as_str(), bank.network, bank.hasher), (0, "", Network::default(), HashAlgorithm::default()));
+    assert_eq!(bank.policy.wallet_grant(&alice.hash), Some((NATIVE_CURRENCY, WALLET_GRANT)));
+    assert_eq!(bank.policy.block_subsidy(0), None);
+}
+
+#[test]
+fn signed_blocks_apply_once_in_sequence() {
+    let (alice, bob) = (TestWallet::new(), TestWallet::new());
+    let (mut bank, tip) = bank_with(&[&alice, &bob]);
+
+    let first = alice.signed(tip, 0, alice.transfer(&bob, 30.0, None));
+    round_trip(&mut bank, &first);
+    assert_eq!(bank.wallets[&alice.hash].sequence, 1);
+
+    // The same signed transfer in a later block, as is and claiming the next sequence number.
+    let mut replay = rsc_testing::block(first.hash, first.data.clone());
+    replay.signature = first.signature.clone();
+    assert_eq!(bank.do_block(&replay).unwrap_err().code(), 2030);
+    replay.sequence = 1;
+    assert_eq!(bank.do_block(&replay).unwrap_err().code(), 2003);
+
+    let skipped = alice.signed(first.hash, 2, alice.transfer(&bob, 30.0, None));
+    assert_eq!(bank.do_block(&skipped).unwrap_err().code(), 2030);
+
+    let second = alice.signed(first.hash, 1, alice.transfer(&bob, 30.0, None));
+    round_trip(&mut bank, &second);
+    assert_eq!(balance(&bank, &bob), WALLET_GRANT + 60.0);
+
+    bank.undo_block(&second).unwrap();
+    bank.undo_block(&first).unwrap();
+    assert_eq!(bank.wallets[&alice.hash].sequence, 0);
+}
+
+#[test]
+fn multisig_signatures_checked_against_the_key_they_name() {
+    let signers = [TestWallet::new(), TestWallet::new(), TestWallet::new()];
+    let bob = TestWallet::new();
+    let (mut bank, tip) = bank_with(&[&bob]);
+
+    let data = MultisigWalletData { pubkeys: signers.iter().map(|s| s.data().pubkey).collect(), threshold: 2, scheme: Scheme::Ed25519 };
+    let wallet = data.hash();
+    let registered = rsc_testing::block(tip, BlockData::MultisigWallet(data));
+    round_trip(&mut bank, &registered);
+
+    let transfer = BlockData::Transaction(TransactionData {
+        from: wallet.to_address(rsc_testing::NETWORK),
+        to: bob.address(),
+        currency: NATIVE_CURRENCY,
+        amount: 10.0,
+        lock: None,
+    });
+    let payload = SigningPayload::new(rsc_testing::CHAIN_ID).build(0, &transfer);
+    let signed_by = |signers_named: &[(usize, usize)]| {
+        let mut block = rsc_testing::block(registered.hash, transfer.clone());
+        let signatures: Vec<_> = signers_named.iter().map(|&(named, by)| (named, signers[by].key.sign(&payload).unwrap())).collect();
+        block.signature = signature::encode_multi(&signatures);
+        block
+    };
+
+    for (signatures, code) in [
+        (vec![(0, 0)], 2011),
+        (vec![(0, 0), (0, 0)], 2010),
+        (vec![(0, 0), (1, 2)], 2003),
+        (vec![(0, 0), (3, 2)], 2009),
+    ] {
+        assert_eq!(bank.do_block(&signed_by(&signatures)).unwrap_err().code(), code, "{:?}", signatures);
+    }
+
+    let block = signed_by(&[(2, 2), (0, 0)]);
+    assert_eq!(bank.signature_jobs(&block).len(), 2);
+    round_trip(&mut bank, &block);
+    assert_eq!(balance(&bank, &bob), WALLET_GRANT + 10.0);
+}
+
+const TOKEN: u64 = 7;
+
+fn issue(issuer: &TestWallet, supply: f64, policy: SupplyPolicy) -> BlockData {
+    BlockData::IssueCurrency(IssueCurrencyData {
+        issuer: issuer.hash.to_string(),
+        currency: TOKEN,
+        symbol: "TOK".to_string(),
+        decimals: 2,
+        supply,
+        policy,
+    })
+}
+
+fn mint(to: &TestWallet, amount: f64) -> BlockData {
+    BlockData::Mint(MintData { currency: TOKEN, to: to.hash.to_string(), amount })
+}
+
+fn burn(amount: f64) -> BlockData {
+    BlockData::Burn(BurnData { currency: TOKEN, amount })
+}
+
+fn tokens(bank: &Bank, wallet: &TestWallet) -> f64 {
+    bank.wallets[&wallet.hash].accounts.get(&TOKEN).copied().unwrap_or_default()
+}
+
+/// Policy of the default bank, also capping the supply of `TOKEN`.
+struct TokenCap(f64);
+
+impl MonetaryPolicy for TokenCap {
+    fn wallet_grant(&self, _wallet: &ByteHash) -> Option<(u64, f64)> {
+        Some((NATIVE_CURRENCY, WALLET_GRANT))
+    }
+
+    fn block_subsidy(&self, _height: u64) -> Option<(u64, f64)> {
+        None
+    }
+
+    fn issuance_cap(&self, currency: u64) -> Option<f64> {
+        Some(self.0).filter(|_| currency == TOKEN)
+    }
+}
+
+#[test]
+fn currency_issue_mint_burn_round_trip() {
+    let (alice, bob) = (TestWallet::new(), TestWallet::new());
+    let (mut bank, tip) = bank_with(&[&alice, &bob]);
+
+    let issued = alice.signed(tip, 0, issue(&alice, 100.0, SupplyPolicy::Mintable));
+    round_trip(&mut bank, &issued);
+    assert_eq!(bank.get_currency(TOKEN).unwrap().issuer, Some(alice.hash));
+
+    let minted = alice.signed(issued.hash, 1, mint(&bob, 50.0));
+    round_trip(&mut bank, &minted);
+
+    let burned = alice.signed(minted.hash, 2, burn(30.0));
+    round_trip(&mut bank, &burned);
+    assert_eq!((tokens(&bank, &alice), tokens(&bank, &bob)), (70.0, 50.0));
+    assert_eq!(bank.auditor.supply(TOKEN), 120.0);
+}
+
+#[test]
+fn currency_rules_enforced() {
+    let (alice, bob) = (TestWallet::new(), TestWallet::new());
+    let (mut bank, tip) = bank_with(&[&alice, &bob]);
+    bank.policy = std::sync::Arc::new(TokenCap(120.0));
+
+    assert_eq!(bank.do_block(&alice.signed(tip, 0, issue(&alice, 150.0, SupplyPolicy::Mintable))).unwrap_err().code(), 2021);
+
+    let fixed = alice.signed(tip, 0, issue(&alice, 100.0, SupplyPolicy::Fixed));
+    bank.do_block(&fixed).unwrap();
+    assert_eq!(bank.do_block(&alice.signed(fixed.hash, 1, mint(&bob, 10.0))).unwrap_err().code(), 2008);
+    assert_eq!(bank.do_block(&alice.signed(fixed.hash, 1, issue(&alice, 10.0, SupplyPolicy::Mintable))).unwrap_err().code(), 2006);
+    bank.undo_block(&fixed).unwrap();
+
+    let mintable = alice.signed(tip, 0, issue(&alice, 100.0, SupplyPolicy::Mintable));
+    bank.do_block(&mintable).unwrap();
+    let before = bank.serialize_state();
+
+    assert_eq!(bank.do_block(&bob.signed(mintable.hash, 1, mint(&bob, 10.0))).unwrap_err().code(), 2003);
+    assert_eq!(bank.do_block(&alice.signed(mintable.hash, 1, mint(&bob, 30.0))).unwrap_err().code(), 2021);
+    assert_eq!(bank.serialize_state(), before);
+
+    bank.do_block(&alice.signed(mintable.hash, 1, mint(&bob, 20.0))).unwrap();
+    assert_eq!(tokens(&bank, &bob), 20.0);
+}
+
+#[test]
+fn failed_issue_undo_keeps_currency() {
+    let (alice, bob) = (TestWallet::new(), TestWallet::new());
+    let (mut bank, tip) = bank_with(&[&alice, &bob]);
+
+    let issued = alice.signed(tip, 0, issue(&alice, 100.0, SupplyPolicy::Fixed));
+    let BlockData::IssueCurrency(data) = &issued.data else { unreachable!() };
+    bank.do_block(&issued).unwrap();
+    let transfer = BlockData::Transaction(TransactionData { from: alice.address(), to: bob.address(), currency: TOKEN, amount: 60.0, lock: None });
+    bank.do_block(&alice.signed(issued.hash, 1, transfer)).unwrap();
+    let before = bank.serialize_state();
+
+    // The issuer no longer holds the whole supply, so the issue can't be taken back.
+    assert_eq!(bank.process_issue_currency_block(&issued, data, true).unwrap_err().code(), 2004);
+    assert!(bank.get_currency(TOKEN).is_ok());
+    assert_eq!(bank.serialize_state(), before);
+}
+
+fn rotate(wallet: &TestWallet, to: &TestWallet) -> BlockData {
+    BlockData::RotateKey(RotateKeyData { wallet: wallet.hash.to_string(), pubkey: to.data().pubkey })
+}
+
+#[test]
+fn rotated_key_replaces_previous_one() {
+    let (alice, bob) = (TestWallet::new(), TestWallet::new());
+    let (mut bank, tip) = bank_with(&[&alice, &bob]);
+    // Alice's wallet signing with the key she rotates to.
+    let rotated = TestWallet { key: TestWallet::new().key, hash: alice.hash };
+
+    let rotation = alice.signed(tip, 0, rotate(&alice, &rotated));
+    round_trip(&mut bank, &rotation);
+    assert_eq!(bank.wallets[&alice.hash].key_history, vec![(bank.height - 1, alice.data().pubkey.into_bytes())]);
+
+    let old_key = alice.signed(rotation.hash, 1, alice.transfer(&bob, 10.0, None));
+    assert_eq!(bank.do_block(&old_key).unwrap_err().code(), 2003);
+    round_trip(&mut bank, &rotated.signed(rotation.hash, 1, rotated.transfer(&bob, 10.0, None)));
+    assert_eq!(balance(&bank, &alice), WALLET_GRANT - 10.0);
+}
+
+#[test]
+fn undo_rotation_restores_previous_key() {
+    let (alice, bob) = (TestWallet::new(), TestWallet::new());
+    let (mut bank, tip) = bank_with(&[&alice, &bob]);
+    let rotated = TestWallet { key: TestWallet::new().key, hash: alice.hash };
+
+    let rotation = alice.signed(tip, 0, rotate(&alice, &rotated));
+    bank.do_block(&rotation).unwrap();
+    let before = bank.serialize_state();
+
+    // Undoing a rotation to a key the wallet doesn't hold leaves it untouched.
+    let mismatched = alice.signed(tip, 0, rotate(&alice, &TestWallet::new()));
+    let BlockData::RotateKey(data) = &mismatched.data else { unreachable!() };
+    assert_eq!(bank.process_rotate_key_block(&mismatched, data, true).unwrap_err().code(), 2013);
+    assert_eq!(bank.serialize_state(), before);
+
+    bank.undo_block(&rotation).unwrap();
+    let wallet = &bank.wallets[&alice.hash];
+    assert_eq!((wallet.pubkey.clone(), wallet.key_history.len()), (alice.data().pubkey.into_bytes(), 0));
+    bank.do_block(&alice.signed(tip, 0, alice.transfer(&bob, 10.0, None))).unwrap();
+}
+
+#[test]
+fn state_hash_independent_of_registration_order() {
+    let (alice, bob) = (TestWallet::new(), TestWallet::new());
+    let (mut first, first_tip) = bank_with(&[&alice, &bob]);
+    let (mut second, second_tip) = bank_with(&[&bob, &alice]);
+    let registered = first.state_hash();
+    assert_eq!(second.state_hash(), registered);
+
+    let transfer = alice.signed(first_tip, 0, alice.transfer(&bob, 30.0, None));
+    first.do_block(&transfer).unwrap();
+    second.do_block(&alice.signed(second_tip, 0, alice.transfer(&bob, 30.0, None))).unwrap();
+    assert_ne!(first.state_hash(), registered);
+    assert_eq!(first.state_hash(), second.state_hash());
+
+    first.undo_block(&transfer).unwrap();
+    assert_eq!(first.state_hash(), registered);
+}
+
+#[test]
+fn rejects_addresses_of_another_network() {
+    let (alice, bob) = (TestWallet::new(), TestWallet::new());
+    let (mut bank, tip) = bank_with(&[&alice, &bob]);
+    let before = bank.serialize_state();
+
+    let transfer = BlockData::Transaction(TransactionData {
+        from: alice.address(),
+        to: bob.hash.to_address(Network::Main),
+        currency: NATIVE_CURRENCY,
+        amount: 10.0,
+        lock: None,
+    });
+    assert_eq!(bank.do_block(&alice.signed(tip, 0, transfer)).unwrap_err().code(), 1016);
+    assert_eq!(bank.serialize_state(), before);
+}
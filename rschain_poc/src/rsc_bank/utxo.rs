@@ -0,0 +1,520 @@
+use std::{collections::{BTreeMap, BTreeSet}, fmt::Display, sync::Arc};
+
+use crate::{rsc_util::{hash::{ByteHash, Hashable}, hasher::HashAlgorithm}, rsc_blockdata::{block_data::{GenesisData, WalletData, UtxoTransactionData, OutPoint, TxOutput, LedgerModel}, BlockData}, rsc_crypto::{signature::Scheme, payload::SigningPayload}, rsc_core::block::Block, rsc_error::{Result, ValidationError}};
+
+use super::{BankError, ledger::Ledger, history::Movement, policy::{self, FaucetPolicy, MonetaryPolicy}};
+
+const BALANCE_TOLERANCE: f64 = 1e-9;
+
+/// Bitcoin-style ledger, where balances are the sum of unspent outputs owned by a wallet.
+#[derive(Clone)]
+pub struct UtxoLedger {
+    pub pubkeys: BTreeMap<ByteHash, (Scheme, Vec<u8>)>,
+    pub unspent: BTreeMap<OutPoint, TxOutput>,
+
+    /// Outputs consumed by applied transactions, kept to restore them on undo.
+    spent: BTreeMap<OutPoint, TxOutput>,
+    height: u64,
+
+    /// Chain ID set by the genesis block, part of every signed payload.
+    chain_id: String,
+
+    /// Hash function set by the genesis block, deriving wallet and transaction identifiers.
+    hasher: HashAlgorithm,
+
+    /// Monetary policy set by the genesis block, deciding the outputs granted to new wallets and coinbase wallets.
+    policy: Arc<dyn MonetaryPolicy>,
+
+    /// Supply created by grants and subsidies per currency, checked against the policy issuance cap.
+    issued: BTreeMap<u64, f64>,
+}
+
+impl Default for UtxoLedger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl UtxoLedger {
+    pub fn new() -> UtxoLedger {
+        UtxoLedger {
+            pubkeys: BTreeMap::new(),
+            unspent: BTreeMap::new(),
+            spent: BTreeMap::new(),
+            height: 0,
+            chain_id: String::new(),
+            hasher: HashAlgorithm::default(),
+            policy: Arc::new(FaucetPolicy::default()),
+            issued: BTreeMap::new(),
+        }
+    }
+
+    pub fn unspent_of(&self, owner: ByteHash) -> Vec<(OutPoint, &TxOutput)> {
+        let owner: String = owner.into();
+        self.unspent
+            .iter()
+            .filter(|(_, o)| o.owner == owner)
+            .map(|(p, o)| (*p, o))
+            .collect()
+    }
+
+    pub fn balance(&self, owner: ByteHash) -> BTreeMap<u64, f64> {
+        let mut balance = BTreeMap::<u64, f64>::new();
+        for (_, output) in self.unspent_of(owner) {
+            *balance.entry(output.currency).or_default() += output.amount;
+        }
+
+        balance
+    }
+
+    /// Picks unspent outputs of `from` covering the amount and sends the remainder back to it as change.
+    pub fn build_transfer(&self, from: ByteHash, to: ByteHash, currency: u64, amount: f64) -> Result<UtxoTransactionData> {
+        let mut inputs = Vec::<OutPoint>::new();
+        let mut collected = 0.0;
+
+        for (point, output) in self.unspent_of(from).into_iter().filter(|(_, o)| o.currency == currency) {
+            if collected >= amount {
+                break;
+            }
+
+            inputs.push(point);
+            collected += output.amount;
+        }
+
+        if collected < amount {
+            Err(BankError::InsufficientCurrency { wallet: from, currency, required: amount, available: collected })?;
+        }
+
+        let mut outputs = vec![TxOutput { owner: to.into(), currency, amount }];
+        if collected > amount {
+            outputs.push(TxOutput { owner: from.into(), currency, amount: collected - amount });
+        }
+
+        Ok(UtxoTransactionData { inputs, outputs })
+    }
+
+    fn check_issuance_cap(&self, currency: u64, amount: f64) -> Result<()> {
+        if let Some(cap) = self.policy.issuance_cap(currency) {
+            let required = self.issued.get(&currency).copied().unwrap_or_default() + amount;
+            if required > cap {
+                Err(BankError::IssuanceCapExceeded { currency, required, cap })?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn issue(&mut self, currency: u64, amount: f64) {
+        *self.issued.entry(currency).or_default() += amount;
+    }
+
+    fn retire(&mut self, currency: u64, amount: f64) {
+        match self.issued.get(&currency).copied().unwrap_or_default() - amount {
+            0.0 => self.issued.remove(&currency),
+            remaining => self.issued.insert(currency, remaining),
+        };
+    }
+
+    /// Pays the block subsidy set by monetary policy to the coinbase wallet, as an output named after the block.
+    fn process_subsidy(&mut self, block: &Block, invert: bool) -> Result<()> {
+        let (Some(coinbase), Some((currency, amount))) = (block.coinbase, self.policy.block_subsidy(self.height)) else {
+            return Ok(());
+        };
+
+        let point = OutPoint { tx: block.hash, index: 0 };
+        if !self.pubkeys.contains_key(&coinbase) {
+            Err(BankError::WalletNotFound { wallet: coinbase })?;
+        }
+
+        if !invert {
+            self.check_issuance_cap(currency, amount)?;
+            if self.unspent.contains_key(&point) {
+                Err(BankError::OutputDuplicate { tx: point.tx, index: point.index })?;
+            }
+
+            self.unspent.insert(point, TxOutput { owner: coinbase.into(), currency, amount });
+            self.issue(currency, amount);
+        } else {
+            self.unspent.remove(&point).ok_or(BankError::OutputNotFound { tx: point.tx, index: point.index })?;
+            self.retire(currency, amount);
+        }
+
+        Ok(())
+    }
+
+    fn process_block(&mut self, block: &Block, invert: bool) -> Result<()> {
+        match &block.data {
+            BlockData::Genesis(data) => self.process_genesis_block(data, invert),
+            BlockData::Wallet(data) => self.process_wallet_block(data, invert),
+            BlockData::UtxoTransaction(data) => self.process_transaction_block(block, data, invert),
+            BlockData::Empty => Ok(()),
+
+            _ => Err(ValidationError::UnsupportedBlockData.into()),
+        }
+    }
+
+    fn process_genesis_block(&mut self, data: &GenesisData, invert: bool) -> Result<()> {
+        self.check_genesis(self.height, data)?;
+
+        if let (false, Some(config)) = (invert, &data.policy) {
+            self.policy = policy::from_config(config)?;
+        }
+
+        if !invert {
+            self.chain_id = data.chain_id.clone();
+            self.hasher = data.hasher;
+        }
+
+        Ok(())
+    }
+
+    fn process_wallet_block(&mut self, data: &WalletData, invert: bool) -> Result<()> {
+        let hash = data.hash_with(self.hasher);
+        let point = OutPoint { tx: hash, index: 0 };
+        let grant = self.policy.wallet_grant(&hash);
+
+        if !invert {
+            if self.pubkeys.contains_key(&hash) {
+                Err(BankError::WalletDuplicate { wallet: hash })?;
+            }
+
+            data.scheme.check_public_key(data.pubkey.as_bytes())?;
+            if let Some((currency, amount)) = grant {
+                self.check_issuance_cap(currency, amount)?;
+                self.unspent.insert(point, TxOutput { owner: hash.into(), currency, amount });
+                self.issue(currency, amount);
+            }
+
+            self.pubkeys.insert(hash, (data.scheme, data.pubkey.clone().into()));
+        } else {
+            if let Some((currency, amount)) = grant {
+                self.unspent.remove(&point).ok_or(BankError::OutputNotFound { tx: point.tx, index: point.index })?;
+                self.retire(currency, amount);
+            }
+
+            self.pubkeys.remove(&hash).ok_or(BankError::WalletNotFound { wallet: hash })?;
+        }
+
+        Ok(())
+    }
+
+    fn process_transaction_block(&mut self, block: &Block, data: &UtxoTransactionData, invert: bool) -> Result<()> {
+        let tx = data.hash_with(self.hasher);
+        let inputs_source = if invert { &self.spent } else { &self.unspent };
+
+        if data.inputs.is_empty() {
+            Err(ValidationError::NoInputs)?;
+        }
+
+        let mut seen = BTreeSet::<&OutPoint>::new();
+        for point in &data.inputs {
+            if !seen.insert(point) {
+                Err(BankError::OutputDuplicate { tx: point.tx, index: point.index })?;
+            }
+        }
+
+        let inputs = data.inputs
+            .iter()
+            .map(|p| inputs_source.get(p).ok_or(BankError::OutputNotFound { tx: p.tx, index: p.index }))
+            .collect::<std::result::Result<Vec<&TxOutput>, _>>()?;
+
+        let owner = &inputs[0].owner;
+        let owner_hash: ByteHash = owner.try_into()?;
+        if inputs.iter().any(|o| &o.owner != owner) {
+            Err(BankError::OwnerMismatch { wallet: owner_hash })?;
+        }
+
+        let (scheme, pubkey) = self.pubkeys.get(&owner_hash).ok_or(BankError::WalletNotFound { wallet: owner_hash })?;
+        let payload = SigningPayload::new(&self.chain_id).build(block.sequence, &block.data);
+        if !scheme.check(pubkey, &payload, &block.signature)? {
+            Err(BankError::SignatureInvalid { wallet: owner_hash })?;
+        }
+
+        let mut balance = BTreeMap::<u64, f64>::new();
+        let mut spent = BTreeMap::<u64, f64>::new();
+        for input in &inputs {
+            *balance.entry(input.currency).or_default() += input.amount;
+        }
+
+        for output in &data.outputs {
+            let output_owner: ByteHash = (&output.owner).try_into()?;
+            if !self.pubkeys.contains_key(&output_owner) {
+                Err(BankError::WalletNotFound { wallet: output_owner })?;
+            }
+
+            if !output.amount.is_finite() || output.amount <= 0.0 {
+                Err(ValidationError::InvalidAmount { amount: output.amount })?;
+            }
+
+            *spent.entry(output.currency).or_default() += output.amount;
+        }
+
+        for currency in balance.keys().chain(spent.keys()) {
+            let required = balance.get(currency).copied().unwrap_or_default();
+            let available = spent.get(currency).copied().unwrap_or_default();
+            if (required - available).abs() > BALANCE_TOLERANCE {
+                Err(BankError::Unbalanced { currency: *currency, required, available })?;
+            }
+        }
+
+        let outputs = data.outputs
+            .iter()
+            .enumerate()
+            .map(|(i, o)| (OutPoint { tx, index: i as u32 }, o));
+
+        if !invert {
+            for (point, output) in outputs {
+                if self.unspent.contains_key(&point) {
+                    Err(BankError::OutputDuplicate { tx: point.tx, index: point.index })?;
+                }
+
+                self.unspent.insert(point, output.clone());
+            }
+
+            for point in &data.inputs {
+                let output = self.unspent.remove(point).expect("precheck");
+                self.spent.insert(*point, output);
+            }
+        } else {
+            for (point, _) in outputs {
+                self.unspent.remove(&point).ok_or(BankError::OutputNotFound { tx: point.tx, index: point.index })?;
+            }
+
+            for point in &data.inputs {
+                let output = self.spent.remove(point).expect("precheck");
+                self.unspent.insert(*point, output);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Ledger for UtxoLedger {
+    const MODEL: LedgerModel = LedgerModel::Utxo;
+
+    fn do_block(&mut self, block: &Block) -> Result<()> {
+        // Undoing a genesis block keeps the configuration it applied, so a failed one has to restore it.
+        let config = (self.policy.clone(), self.chain_id.clone(), self.hasher);
+
+        self.process_block(block, false)?;
+        if let Err(e) = self.process_subsidy(block, false) {
+            self.process_block(block, true).expect("undo of applied block");
+            (self.policy, self.chain_id, self.hasher) = config;
+            return Err(e);
+        }
+
+        self.height += 1;
+        Ok(())
+    }
+
+    fn undo_block(&mut self, block: &Block) -> Result<()> {
+        self.height = self.height.checked_sub(1).expect("undo past genesis");
+        self.process_subsidy(block, true)?;
+        self.process_block(block, true)
+    }
+
+    fn movements(&self, block: &Block) -> Vec<Movement> {
+        match &block.data {
+            BlockData::Wallet(data) => {
+                let wallet = data.hash_with(self.hasher);
+                self.policy
+                    .wallet_grant(&wallet)
+                    .map(|(currency, amount)| Movement::incoming(wallet, None, currency, amount))
+                    .into_iter()
+                    .collect()
+            },
+            BlockData::UtxoTransaction(data) => {
+                let owner = data.inputs.first().and_then(|p| self.unspent.get(p)).map(|o| &o.owner);
+                let Some(from) = owner.and_then(|o| ByteHash::try_from(o).ok()) else {
+                    return vec![];
+                };
+
+                let mut movements = Vec::<Movement>::new();
+                for output in data.outputs.iter().filter(|o| Some(&o.owner) != owner) {
+                    if let Ok(to) = ByteHash::try_from(&output.owner) {
+                        movements.push(Movement::outgoing(from, Some(to), output.currency, output.amount));
+                        movements.push(Movement::incoming(to, Some(from), output.currency, output.amount));
+                    }
+                }
+
+                movements
+            },
+
+            _ => vec![],
+        }
+    }
+}
+
+impl Display for UtxoLedger {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for hash in self.pubkeys.keys() {
+            f.write_fmt(format_args!("{} (", hash))?;
+            for (c, a) in self.balance(*hash) {
+                f.write_fmt(format_args!("{}={},", c, a))?;
+            }
+            f.write_str(")")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rsc_bank::{WALLET_GRANT, currency::NATIVE_CURRENCY};
+    use crate::rsc_blockdata::block_data::MonetaryPolicyConfig;
+    use crate::rsc_error::ErrorCode;
+    use crate::rsc_testing::{self, TestWallet};
+
+    type State = (BTreeMap<ByteHash, (Scheme, Vec<u8>)>, BTreeMap<OutPoint, TxOutput>, BTreeMap<OutPoint, TxOutput>, BTreeMap<u64, f64>, u64);
+
+    fn state(ledger: &UtxoLedger) -> State {
+        (ledger.pubkeys.clone(), ledger.unspent.clone(), ledger.spent.clone(), ledger.issued.clone(), ledger.height)
+    }
+
+    /// Ledger past its genesis block with the wallets registered, along with the hash of the last block.
+    fn ledger_with(wallets: &[&TestWallet]) -> (UtxoLedger, ByteHash) {
+        let mut ledger = UtxoLedger::new();
+        let genesis = rsc_testing::genesis(LedgerModel::Utxo, None);
+        ledger.do_block(&genesis).unwrap();
+
+        let mut tip = genesis.hash;
+        for wallet in wallets {
+            let block = wallet.register(tip);
+            ledger.do_block(&block).unwrap();
+            tip = block.hash;
+        }
+
+        (ledger, tip)
+    }
+
+    fn native(ledger: &UtxoLedger, wallet: &TestWallet) -> f64 {
+        ledger.balance(wallet.hash).get(&NATIVE_CURRENCY).copied().unwrap_or_default()
+    }
+
+    #[test]
+    fn transfer_round_trip() {
+        let (alice, bob) = (TestWallet::new(), TestWallet::new());
+        let (mut ledger, tip) = ledger_with(&[&alice, &bob]);
+
+        let data = ledger.build_transfer(alice.hash, bob.hash, NATIVE_CURRENCY, 30.0).unwrap();
+        let block = alice.signed(tip, 0, BlockData::UtxoTransaction(data));
+
+        let before = state(&ledger);
+        ledger.do_block(&block).unwrap();
+        let after = state(&ledger);
+        assert_eq!(native(&ledger, &alice), WALLET_GRANT - 30.0);
+        assert_eq!(native(&ledger, &bob), WALLET_GRANT + 30.0);
+
+        ledger.undo_block(&block).unwrap();
+        assert_eq!(state(&ledger), before);
+
+        ledger.do_block(&block).unwrap();
+        assert_eq!(state(&ledger), after);
+    }
+
+    #[test]
+    fn spent_output_spendable_again_after_undo() {
+        let (alice, bob) = (TestWallet::new(), TestWallet::new());
+        let (mut ledger, tip) = ledger_with(&[&alice, &bob]);
+
+        let data = ledger.build_transfer(alice.hash, bob.hash, NATIVE_CURRENCY, 30.0).unwrap();
+        let first = alice.signed(tip, 0, BlockData::UtxoTransaction(data.clone()));
+        ledger.do_block(&first).unwrap();
+
+        let mut double = data;
+        double.outputs = vec![TxOutput { owner: bob.hash.into(), currency: NATIVE_CURRENCY, amount: WALLET_GRANT }];
+        let double = alice.signed(first.hash, 1, BlockData::UtxoTransaction(double));
+        assert_eq!(ledger.do_block(&double).unwrap_err().code(), 2026);
+
+        ledger.undo_block(&first).unwrap();
+        ledger.do_block(&double).unwrap();
+        assert_eq!(native(&ledger, &alice), 0.0);
+        assert_eq!(native(&ledger, &bob), 2.0 * WALLET_GRANT);
+    }
+
+    #[test]
+    fn wallet_registration_round_trip() {
+        let alice = TestWallet::new();
+        let (mut ledger, tip) = ledger_with(&[]);
+
+        let before = state(&ledger);
+        let block = alice.register(tip);
+        ledger.do_block(&block).unwrap();
+        assert_eq!(native(&ledger, &alice), WALLET_GRANT);
+
+        ledger.undo_block(&block).unwrap();
+        assert_eq!(state(&ledger), before);
+
+        let invalid = rsc_testing::block(tip, BlockData::Wallet(WalletData { pubkey: "00".repeat(31), scheme: Scheme::Ed25519 }));
+        assert_eq!(ledger.do_block(&invalid).unwrap_err().code(), 1004);
+        assert_eq!(state(&ledger), before);
+    }
+
+    #[test]
+    fn wallet_grants_follow_genesis_policy() {
+        let (alice, bob) = (TestWallet::new(), TestWallet::new());
+        let mut ledger = UtxoLedger::new();
+        let premine = MonetaryPolicyConfig::Premine { wallet: alice.hash.to_string(), amount: 1000.0 };
+        let genesis = rsc_testing::genesis(LedgerModel::Utxo, Some(premine));
+        ledger.do_block(&genesis).unwrap();
+
+        let mut tip = genesis.hash;
+        for wallet in [&alice, &bob] {
+            let block = wallet.register(tip);
+            let before = state(&ledger);
+            ledger.do_block(&block).unwrap();
+            ledger.undo_block(&block).unwrap();
+            assert_eq!(state(&ledger), before);
+
+            ledger.do_block(&block).unwrap();
+            tip = block.hash;
+        }
+
+        assert_eq!((native(&ledger, &alice), native(&ledger, &bob)), (1000.0, 0.0));
+        assert!(ledger.unspent_of(bob.hash).is_empty());
+    }
+
+    #[test]
+    fn subsidy_paid_to_coinbase_wallet() {
+        let alice = TestWallet::new();
+        let mut ledger = UtxoLedger::new();
+        let genesis = rsc_testing::genesis(LedgerModel::Utxo, Some(MonetaryPolicyConfig::NoGrant { subsidy: 50.0 }));
+        ledger.do_block(&genesis).unwrap();
+        let registered = alice.register(genesis.hash);
+        ledger.do_block(&registered).unwrap();
+
+        let mut block = Block::new(registered.hash, BlockData::Empty);
+        block.coinbase = Some(alice.hash);
+        block.update_nonce(0, block.hash_algorithm(HashAlgorithm::default()));
+
+        let before = state(&ledger);
+        ledger.do_block(&block).unwrap();
+        assert_eq!(native(&ledger, &alice), 50.0);
+
+        ledger.undo_block(&block).unwrap();
+        assert_eq!(state(&ledger), before);
+
+        block.coinbase = Some(TestWallet::new().hash);
+        block.update_nonce(0, block.hash_algorithm(HashAlgorithm::default()));
+        assert_eq!(ledger.do_block(&block).unwrap_err().code(), 2001);
+        assert_eq!(state(&ledger), before);
+    }
+
+    #[test]
+    fn grants_capped_by_policy() {
+        let alice = TestWallet::new();
+        let mut ledger = UtxoLedger::new();
+        let premine = MonetaryPolicyConfig::Premine { wallet: alice.hash.to_string(), amount: 1000.0 };
+        let genesis = rsc_testing::genesis(LedgerModel::Utxo, Some(premine));
+        ledger.do_block(&genesis).unwrap();
+
+        // The premine grants its whole cap, so any supply already issued leaves no room for it.
+        ledger.issue(NATIVE_CURRENCY, 1.0);
+        let before = state(&ledger);
+        assert_eq!(ledger.do_block(&alice.register(genesis.hash)).unwrap_err().code(), 2021);
+        assert_eq!(state(&ledger), before);
+    }
+}
@@ -1,58 +1,575 @@
 use std::fmt::Display;
 
 use serde::{Serialize, Deserialize};
-use sha2::{Sha256, Digest};
 use substring::Substring;
 
-use crate::rsc_util::hash::{Hashable, ByteHash};
+use crate::rsc_util::{hash::{Hashable, ByteHash}, address::{Address, Network}, hasher::{ChainHasher, HashAlgorithm}};
+use crate::rsc_crypto::signature::Scheme;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LedgerModel {
+    Account,
+    Utxo,
+}
+
+/// Built-in monetary policies that can be selected by the genesis block.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum MonetaryPolicyConfig {
+    Faucet { grant: f64, subsidy: f64 },
+    NoGrant { subsidy: f64 },
+    Premine { wallet: String, amount: f64 },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GenesisData {
+    pub ledger: LedgerModel,
+
+    #[serde(default)]
+    pub policy: Option<MonetaryPolicyConfig>,
+
+    /// Chain ID block signatures are bound to, empty for chains created before it existed.
+    #[serde(default)]
+    pub chain_id: String,
+
+    /// Network whose addresses transactions on the chain must use.
+    #[serde(default)]
+    pub network: Network,
+
+    /// Hash function of the chain, used for block hashes and every hash the ledger derives from block data.
+    #[serde(default)]
+    pub hasher: HashAlgorithm,
+}
+
+impl Hashable for GenesisData {
+    fn hash_with(&self, algorithm: HashAlgorithm) -> ByteHash {
+        let mut hasher = algorithm.hasher();
+        hasher.update([self.ledger as u8]);
+        hasher.update(serde_json::to_vec(&self.policy).expect("policy serialization"));
+        if !self.chain_id.is_empty() {
+            hasher.update(&self.chain_id);
+        }
+
+        if self.network != Network::default() {
+            hasher.update(self.network.prefix());
+        }
+
+        if self.hasher != HashAlgorithm::default() {
+            hasher.update(serde_json::to_vec(&self.hasher).expect("hasher serialization"));
+        }
+
+        hasher.finalize()
+    }
+}
+
+impl Display for GenesisData {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!("GENS {:?}", self.ledger))
+    }
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WalletData {
     pub pubkey: String,
+
+    #[serde(default = "Scheme::legacy")]
+    pub scheme: Scheme,
 }
 
+/// Only the public key is hashed, which keeps hashes of wallets created before the scheme tag unchanged.
 impl Hashable for WalletData {
-    fn hash(&self) -> ByteHash {
-        let mut hasher = Sha256::new();
+    fn hash_with(&self, algorithm: HashAlgorithm) -> ByteHash {
+        let mut hasher = algorithm.hasher();
         hasher.update(&self.pubkey);
 
-        return hasher.finalize().try_into().expect("hasher/Hash incompat");
+        hasher.finalize()
     }
 }
 
+/// Start of a public key past any PEM armor, shown in place of the wallet hash, which depends on the chain's hash function.
+fn key_prefix(pubkey: &str) -> &str {
+    pubkey.lines().find(|l| !l.starts_with("-----")).unwrap_or(pubkey).substring(0, 8)
+}
+
 impl Display for WalletData {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let hash_string = self.hash().to_string();
+        f.write_fmt(format_args!("WALLET {:?} {}", self.scheme, key_prefix(&self.pubkey)))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MultisigWalletData {
+    pub pubkeys: Vec<String>,
+    pub threshold: u32,
+
+    #[serde(default = "Scheme::legacy")]
+    pub scheme: Scheme,
+}
+
+impl Hashable for MultisigWalletData {
+    fn hash_with(&self, algorithm: HashAlgorithm) -> ByteHash {
+        let mut hasher = algorithm.hasher();
+        for pubkey in &self.pubkeys {
+            hasher.update(pubkey);
+        }
+        hasher.update(self.threshold.to_ne_bytes());
+
+        hasher.finalize()
+    }
+}
+
+impl Display for MultisigWalletData {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let first_key = self.pubkeys.first().map_or("", |k| key_prefix(k));
+
+        f.write_fmt(format_args!("MSIG {}/{} {:?} {}", self.threshold, self.pubkeys.len(), self.scheme, first_key))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RotateKeyData {
+    pub wallet: String,
+    pub pubkey: String,
+}
+
+impl Hashable for RotateKeyData {
+    fn hash_with(&self, algorithm: HashAlgorithm) -> ByteHash {
+        let mut hasher = algorithm.hasher();
+        hasher.update(&self.wallet);
+        hasher.update(&self.pubkey);
+
+        hasher.finalize()
+    }
+}
+
+impl Display for RotateKeyData {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!("ROTK {}", self.wallet.substring(0, 8)))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Lock {
+    Height(u64),
+    Time(u64),
+}
+
+impl Lock {
+    pub fn is_unlocked(&self, height: u64, timestamp: u64) -> bool {
+        match self {
+            Lock::Height(unlock_height) => height >= *unlock_height,
+            Lock::Time(unlock_time) => timestamp >= *unlock_time,
+        }
+    }
+}
 
-        f.write_fmt(format_args!("WALLET {}", hash_string.substring(0, 8)))
+impl Display for Lock {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Lock::Height(height) => f.write_fmt(format_args!("h{}", height)),
+            Lock::Time(timestamp) => f.write_fmt(format_args!("t{}", timestamp)),
+        }
     }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TransactionData {
+    pub from: Address,
+    pub to: Address,
+    pub currency: u64,
+    pub amount: f64,
+
+    #[serde(default)]
+    pub lock: Option<Lock>,
+}
+
+impl Hashable for TransactionData {
+    fn hash_with(&self, algorithm: HashAlgorithm) -> ByteHash {
+        let mut hasher = algorithm.hasher();
+        hasher.update(self.from.to_string());
+        hasher.update(self.to.to_string());
+        hasher.update(self.currency.to_ne_bytes());
+        hasher.update(self.amount.to_ne_bytes());
+
+        match self.lock {
+            Some(Lock::Height(height)) => { hasher.update(b"h"); hasher.update(height.to_ne_bytes()) },
+            Some(Lock::Time(timestamp)) => { hasher.update(b"t"); hasher.update(timestamp.to_ne_bytes()) },
+            None => {},
+        }
+
+        hasher.finalize()
+    }
+}
+
+impl Display for TransactionData {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let from = String::from(self.from.hash());
+        let to = String::from(self.to.hash());
+        let (from, to) = (from.substring(0, 8), to.substring(0, 8));
+
+        f.write_fmt(format_args!("TRAN of {} ({}) {} => {}", self.currency, self.amount, from, to))?;
+        match self.lock {
+            Some(lock) => f.write_fmt(format_args!(" until {}", lock)),
+            None => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransferLeg {
+    pub to: String,
+    pub currency: u64,
+    pub amount: f64,
+}
+
+/// Transfers from one sender to many recipients, applied all together or not at all.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchTransferData {
     pub from: String,
+    pub legs: Vec<TransferLeg>,
+}
+
+impl Hashable for BatchTransferData {
+    fn hash_with(&self, algorithm: HashAlgorithm) -> ByteHash {
+        let mut hasher = algorithm.hasher();
+        hasher.update(&self.from);
+
+        for leg in &self.legs {
+            hasher.update(&leg.to);
+            hasher.update(leg.currency.to_ne_bytes());
+            hasher.update(leg.amount.to_ne_bytes());
+        }
+
+        hasher.finalize()
+    }
+}
+
+impl Display for BatchTransferData {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!("BTRN {} => ", self.from.substring(0, 8)))?;
+        for leg in &self.legs {
+            f.write_fmt(format_args!("{} of {} ({}),", leg.to.substring(0, 8), leg.currency, leg.amount))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Allows the spender to transfer up to the amount out of the owner's wallet, replacing any previous allowance.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApproveData {
+    pub owner: String,
+    pub spender: String,
+    pub currency: u64,
+    pub amount: f64,
+}
+
+impl Hashable for ApproveData {
+    fn hash_with(&self, algorithm: HashAlgorithm) -> ByteHash {
+        let mut hasher = algorithm.hasher();
+        hasher.update(&self.owner);
+        hasher.update(&self.spender);
+        hasher.update(self.currency.to_ne_bytes());
+        hasher.update(self.amount.to_ne_bytes());
+
+        hasher.finalize()
+    }
+}
+
+impl Display for ApproveData {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let owner = self.owner.substring(0, 8);
+        let spender = self.spender.substring(0, 8);
+
+        f.write_fmt(format_args!("APPR of {} ({}) {} => {}", self.currency, self.amount, owner, spender))
+    }
+}
+
+/// Transfer out of the owner's wallet signed by the spender, within the allowance it was approved.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransferFromData {
+    pub owner: String,
+    pub spender: String,
     pub to: String,
     pub currency: u64,
     pub amount: f64,
 }
 
-impl Hashable for TransactionData {
-    fn hash(&self) -> ByteHash {
-        let mut hasher = Sha256::new();
+impl Hashable for TransferFromData {
+    fn hash_with(&self, algorithm: HashAlgorithm) -> ByteHash {
+        let mut hasher = algorithm.hasher();
+        hasher.update(&self.owner);
+        hasher.update(&self.spender);
+        hasher.update(&self.to);
+        hasher.update(self.currency.to_ne_bytes());
+        hasher.update(self.amount.to_ne_bytes());
+
+        hasher.finalize()
+    }
+}
+
+impl Display for TransferFromData {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let owner = self.owner.substring(0, 8);
+        let spender = self.spender.substring(0, 8);
+        let to = self.to.substring(0, 8);
+
+        f.write_fmt(format_args!("TRFR of {} ({}) {} => {} by {}", self.currency, self.amount, owner, to, spender))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SupplyPolicy {
+    Fixed,
+    Mintable,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IssueCurrencyData {
+    pub issuer: String,
+    pub currency: u64,
+    pub symbol: String,
+    pub decimals: u8,
+    pub supply: f64,
+    pub policy: SupplyPolicy,
+}
+
+impl Hashable for IssueCurrencyData {
+    fn hash_with(&self, algorithm: HashAlgorithm) -> ByteHash {
+        let mut hasher = algorithm.hasher();
+        hasher.update(&self.issuer);
+        hasher.update(self.currency.to_ne_bytes());
+        hasher.update(&self.symbol);
+        hasher.update(self.decimals.to_ne_bytes());
+        hasher.update(self.supply.to_ne_bytes());
+        hasher.update([self.policy as u8]);
+
+        hasher.finalize()
+    }
+}
+
+impl Display for IssueCurrencyData {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let issuer = self.issuer.substring(0, 8);
+
+        f.write_fmt(format_args!("ISSU of {} {} ({}, {:?}) by {}", self.currency, self.symbol, self.supply, self.policy, issuer))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MintData {
+    pub currency: u64,
+    pub to: String,
+    pub amount: f64,
+}
+
+impl Hashable for MintData {
+    fn hash_with(&self, algorithm: HashAlgorithm) -> ByteHash {
+        let mut hasher = algorithm.hasher();
+        hasher.update(self.currency.to_ne_bytes());
+        hasher.update(&self.to);
+        hasher.update(self.amount.to_ne_bytes());
+
+        hasher.finalize()
+    }
+}
+
+impl Display for MintData {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let to = self.to.substring(0, 8);
+
+        f.write_fmt(format_args!("MINT of {} ({}) => {}", self.currency, self.amount, to))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BurnData {
+    pub currency: u64,
+    pub amount: f64,
+}
+
+impl Hashable for BurnData {
+    fn hash_with(&self, algorithm: HashAlgorithm) -> ByteHash {
+        let mut hasher = algorithm.hasher();
+        hasher.update(self.currency.to_ne_bytes());
+        hasher.update(self.amount.to_ne_bytes());
+
+        hasher.finalize()
+    }
+}
+
+impl Display for BurnData {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!("BURN of {} ({})", self.currency, self.amount))
+    }
+}
+
+/// Bonds native currency of the wallet as stake.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StakeData {
+    pub wallet: String,
+    pub amount: f64,
+}
+
+impl Hashable for StakeData {
+    fn hash_with(&self, algorithm: HashAlgorithm) -> ByteHash {
+        let mut hasher = algorithm.hasher();
+        hasher.update(&self.wallet);
+        hasher.update(self.amount.to_ne_bytes());
+
+        hasher.finalize()
+    }
+}
+
+impl Display for StakeData {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!("STAK ({}) {}", self.amount, self.wallet.substring(0, 8)))
+    }
+}
+
+/// Unbonds stake of the wallet, which becomes spendable again after the unbonding period.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UnstakeData {
+    pub wallet: String,
+    pub amount: f64,
+}
+
+impl Hashable for UnstakeData {
+    fn hash_with(&self, algorithm: HashAlgorithm) -> ByteHash {
+        let mut hasher = algorithm.hasher();
+        hasher.update(&self.wallet);
+        hasher.update(self.amount.to_ne_bytes());
+
+        hasher.finalize()
+    }
+}
+
+impl Display for UnstakeData {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!("USTK ({}) {}", self.amount, self.wallet.substring(0, 8)))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HtlcLockData {
+    pub from: String,
+    pub to: String,
+    pub currency: u64,
+    pub amount: f64,
+    pub hashlock: String,
+    pub timeout: Lock,
+}
+
+impl Hashable for HtlcLockData {
+    fn hash_with(&self, algorithm: HashAlgorithm) -> ByteHash {
+        let mut hasher = algorithm.hasher();
         hasher.update(&self.from);
         hasher.update(&self.to);
         hasher.update(self.currency.to_ne_bytes());
         hasher.update(self.amount.to_ne_bytes());
+        hasher.update(&self.hashlock);
+        hasher.update(self.timeout.to_string());
 
-        return hasher.finalize().try_into().expect("hasher/Hash incompat");
+        hasher.finalize()
     }
 }
 
-impl Display for TransactionData {
+impl Display for HtlcLockData {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let from = self.from.substring(0, 8);
         let to = self.to.substring(0, 8);
+        let hashlock = self.hashlock.substring(0, 8);
+
+        f.write_fmt(format_args!("HTLC of {} ({}) {} => {} under {} until {}", self.currency, self.amount, from, to, hashlock, self.timeout))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HtlcClaimData {
+    pub htlc: String,
+    pub preimage: String,
+}
+
+impl Hashable for HtlcClaimData {
+    fn hash_with(&self, algorithm: HashAlgorithm) -> ByteHash {
+        let mut hasher = algorithm.hasher();
+        hasher.update(&self.htlc);
+        hasher.update(&self.preimage);
+
+        hasher.finalize()
+    }
+}
+
+impl Display for HtlcClaimData {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!("CLAM {}", self.htlc.substring(0, 8)))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HtlcRefundData {
+    pub htlc: String,
+}
+
+impl Hashable for HtlcRefundData {
+    fn hash_with(&self, algorithm: HashAlgorithm) -> ByteHash {
+        let mut hasher = algorithm.hasher();
+        hasher.update(&self.htlc);
+
+        hasher.finalize()
+    }
+}
+
+impl Display for HtlcRefundData {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!("RFND {}", self.htlc.substring(0, 8)))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct OutPoint {
+    pub tx: ByteHash,
+    pub index: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TxOutput {
+    pub owner: String,
+    pub currency: u64,
+    pub amount: f64,
+}
+
+/// Spends unspent outputs of a single owner, change has to be sent back explicitly as one of the outputs.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UtxoTransactionData {
+    pub inputs: Vec<OutPoint>,
+    pub outputs: Vec<TxOutput>,
+}
+
+impl Hashable for UtxoTransactionData {
+    fn hash_with(&self, algorithm: HashAlgorithm) -> ByteHash {
+        let mut hasher = algorithm.hasher();
+        for input in &self.inputs {
+            hasher.update(input.tx.to_ne_bytes());
+            hasher.update(input.index.to_ne_bytes());
+        }
+
+        for output in &self.outputs {
+            hasher.update(&output.owner);
+            hasher.update(output.currency.to_ne_bytes());
+            hasher.update(output.amount.to_ne_bytes());
+        }
+
+        hasher.finalize()
+    }
+}
+
+impl Display for UtxoTransactionData {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!("UTXO {} => ", self.inputs.len()))?;
+        for output in &self.outputs {
+            f.write_fmt(format_args!("{} of {} ({}),", output.owner.substring(0, 8), output.currency, output.amount))?;
+        }
 
-        return f.write_fmt(format_args!("TRAN of {} ({}) {} => {}", self.currency, self.amount, from, to));
+        Ok(())
     }
 }
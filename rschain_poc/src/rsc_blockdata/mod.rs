@@ -5,25 +5,59 @@ use std::fmt;
 use serde::Deserialize;
 use serde::Serialize;
 
-use crate::rsc_util::hash::{ByteHash, Hashable};
-use crate::rsc_blockdata::block_data::TransactionData;
-use crate::rsc_blockdata::block_data::WalletData;
+use crate::rsc_util::{hash::{ByteHash, Hashable}, hasher::HashAlgorithm};
+use crate::rsc_blockdata::block_data::{GenesisData, TransactionData, BatchTransferData, UtxoTransactionData};
+use crate::rsc_blockdata::block_data::{ApproveData, TransferFromData};
+use crate::rsc_blockdata::block_data::{WalletData, MultisigWalletData, RotateKeyData};
+use crate::rsc_blockdata::block_data::{IssueCurrencyData, MintData, BurnData};
+use crate::rsc_blockdata::block_data::{StakeData, UnstakeData};
+use crate::rsc_blockdata::block_data::{HtlcLockData, HtlcClaimData, HtlcRefundData};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum BlockData {
     Empty,
+    Genesis(GenesisData),
     Wallet(WalletData),
+    MultisigWallet(MultisigWalletData),
+    RotateKey(RotateKeyData),
     Transaction(TransactionData),
+    BatchTransfer(BatchTransferData),
+    Approve(ApproveData),
+    TransferFrom(TransferFromData),
+    IssueCurrency(IssueCurrencyData),
+    Mint(MintData),
+    Burn(BurnData),
+    Stake(StakeData),
+    Unstake(UnstakeData),
+    HtlcLock(HtlcLockData),
+    HtlcClaim(HtlcClaimData),
+    HtlcRefund(HtlcRefundData),
+    UtxoTransaction(UtxoTransactionData),
 }
 
 impl fmt::Display for BlockData {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
+            BlockData::Genesis(data) => f.write_fmt(format_args!("{}", data)),
             BlockData::Wallet(data) => f.write_fmt(format_args!("{}", data)),
+            BlockData::MultisigWallet(data) => f.write_fmt(format_args!("{}", data)),
+            BlockData::RotateKey(data) => f.write_fmt(format_args!("{}", data)),
             BlockData::Transaction(data) => f.write_fmt(format_args!("{}", data)),
+            BlockData::BatchTransfer(data) => f.write_fmt(format_args!("{}", data)),
+            BlockData::Approve(data) => f.write_fmt(format_args!("{}", data)),
+            BlockData::TransferFrom(data) => f.write_fmt(format_args!("{}", data)),
+            BlockData::IssueCurrency(data) => f.write_fmt(format_args!("{}", data)),
+            BlockData::Mint(data) => f.write_fmt(format_args!("{}", data)),
+            BlockData::Burn(data) => f.write_fmt(format_args!("{}", data)),
+            BlockData::Stake(data) => f.write_fmt(format_args!("{}", data)),
+            BlockData::Unstake(data) => f.write_fmt(format_args!("{}", data)),
+            BlockData::HtlcLock(data) => f.write_fmt(format_args!("{}", data)),
+            BlockData::HtlcClaim(data) => f.write_fmt(format_args!("{}", data)),
+            BlockData::HtlcRefund(data) => f.write_fmt(format_args!("{}", data)),
+            BlockData::UtxoTransaction(data) => f.write_fmt(format_args!("{}", data)),
 
             _ => serde_json::to_string(&self)
-                    .map_err(|_| fmt::Error::default())
+                    .map_err(|_| fmt::Error)
                     .and_then(|js| f.write_str(&js)),
         }
     }
@@ -36,11 +70,26 @@ impl From<&BlockData> for Vec<u8> {
 }
 
 impl Hashable for BlockData {
-    fn hash(&self) -> ByteHash {
+    fn hash_with(&self, algorithm: HashAlgorithm) -> ByteHash {
         match self {
             BlockData::Empty => ByteHash::new(),
-            BlockData::Wallet(data) => data.hash(),
-            BlockData::Transaction(data) => data.hash(),
+            BlockData::Genesis(data) => data.hash_with(algorithm),
+            BlockData::Wallet(data) => data.hash_with(algorithm),
+            BlockData::MultisigWallet(data) => data.hash_with(algorithm),
+            BlockData::RotateKey(data) => data.hash_with(algorithm),
+            BlockData::Transaction(data) => data.hash_with(algorithm),
+            BlockData::BatchTransfer(data) => data.hash_with(algorithm),
+            BlockData::Approve(data) => data.hash_with(algorithm),
+            BlockData::TransferFrom(data) => data.hash_with(algorithm),
+            BlockData::IssueCurrency(data) => data.hash_with(algorithm),
+            BlockData::Mint(data) => data.hash_with(algorithm),
+            BlockData::Burn(data) => data.hash_with(algorithm),
+            BlockData::Stake(data) => data.hash_with(algorithm),
+            BlockData::Unstake(data) => data.hash_with(algorithm),
+            BlockData::HtlcLock(data) => data.hash_with(algorithm),
+            BlockData::HtlcClaim(data) => data.hash_with(algorithm),
+            BlockData::HtlcRefund(data) => data.hash_with(algorithm),
+            BlockData::UtxoTransaction(data) => data.hash_with(algorithm),
         }
     }
 }
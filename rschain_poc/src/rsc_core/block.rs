@@ -1,10 +1,10 @@
 use core::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
 use substring::Substring;
 
 use serde::{Serialize, Deserialize};
-use sha2::{Sha256, Digest};
 
-use crate::rsc_util::hash::{ByteHash, Hashable};
+use crate::rsc_util::{hash::{ByteHash, Hashable}, hasher::{ChainHasher, HashAlgorithm}};
 use crate::rsc_blockdata::BlockData;
 
 pub type Nonce = u64;
@@ -14,8 +14,22 @@ pub struct Block {
     pub hash: ByteHash,
     pub previous_hash: ByteHash,
     pub nonce: Nonce,
+
+    /// Varied by miners once every nonce has been tried.
+    #[serde(default)]
+    pub extra_nonce: Nonce,
+
+    pub timestamp: u64,
     pub signature: String,
 
+    /// Number of blocks the signer signed before this one, covered by the signature so it applies only once.
+    #[serde(default)]
+    pub sequence: u64,
+
+    /// Wallet credited with the block subsidy, if any.
+    #[serde(default)]
+    pub coinbase: Option<ByteHash>,
+
     pub data: BlockData,
 }
 
@@ -24,16 +38,44 @@ impl Block {
         Block {
             hash: ByteHash::new(),
             nonce: 0,
+            extra_nonce: 0,
+            timestamp: now(),
             previous_hash,
             data,
             signature: String::new(),
+            sequence: 0,
+            coinbase: None,
         }
     }
 
-    pub fn update_nonce(&mut self, nonce: Nonce) {
+    pub fn update_nonce(&mut self, nonce: Nonce, algorithm: HashAlgorithm) {
         self.nonce = nonce;
-        self.hash = self.hash();
+        self.hash = self.hash_with(algorithm);
+    }
+
+    /// Moves the header on to a fresh extra nonce, bringing the timestamp up to date.
+    pub fn refresh(&mut self, extra_nonce: Nonce) {
+        self.extra_nonce = extra_nonce;
+        self.timestamp = self.timestamp.max(now());
+    }
+
+    /// Hash function of the block on a chain hashed with `chain_hasher`, a genesis block selecting its own.
+    pub fn hash_algorithm(&self, chain_hasher: HashAlgorithm) -> HashAlgorithm {
+        match &self.data {
+            BlockData::Genesis(data) => data.hasher,
+            _ => chain_hasher,
+        }
     }
+
+    /// Whether the hash starts with at least `difficulty` zero bytes.
+    pub fn meets_difficulty(&self, difficulty: usize) -> bool {
+        (0..difficulty).all(|i| self.hash[i] == 0u8)
+    }
+}
+
+/// Current time in seconds since the Unix epoch, as block timestamps hold it.
+pub(crate) fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
 }
 
 impl fmt::Display for Block {
@@ -52,21 +94,24 @@ impl fmt::Display for Block {
 }
 
 impl Hashable for Block {
-    fn hash(&self) -> ByteHash {
-        let mut hasher = Sha256::new();
-        let mut data = Vec::<u8>::new();
-
-        data.extend(self.previous_hash.to_ne_bytes().iter());
-        data.extend(self.nonce.to_ne_bytes());
-        data.extend::<Vec<u8>>((&self.data).into());
+    fn hash_with(&self, algorithm: HashAlgorithm) -> ByteHash {
+        let mut hasher = algorithm.hasher();
 
         hasher.update(self.previous_hash.to_ne_bytes());
         hasher.update(self.nonce.to_ne_bytes());
+        hasher.update(self.extra_nonce.to_ne_bytes());
+
+        hasher.update(self.timestamp.to_ne_bytes());
+        hasher.update(self.sequence.to_ne_bytes());
+
+        if let Some(coinbase) = self.coinbase {
+            hasher.update(coinbase.to_ne_bytes());
+        }
 
         let data_bytes: Vec<u8> = (&self.data).into();
         hasher.update(data_bytes);
 
-        hasher.finalize().try_into().expect("hasher/Hash incompat")
+        hasher.finalize()
     }
 }
 
@@ -2,64 +2,121 @@ use core::fmt;
 use std::rc::Rc;
 use std::fmt::Debug;
 
-use crate::rsc_bank::Bank;
-use crate::rsc_blockdata::BlockData;
-use crate::rsc_util::hash::{ByteHash, Hashable};
+use crate::rsc_bank::{Bank, ledger::Ledger, history::HistoryIndex};
+use crate::rsc_error::{Error, ErrorCode, Result};
+use crate::rsc_util::hash::ByteHash;
 
 use super::block::Block;
 use super::chain_iter::BlockchainIterator;
 
 #[derive(thiserror::Error, Debug)]
-pub enum BlockchainError {
-    #[error("no attach point")]
-    NoAttachPoint,
+pub enum StoreError {
+    #[error("NoAttachPoint: block {block} extends unknown block {previous}")]
+    NoAttachPoint { block: ByteHash, previous: ByteHash },
+
+    #[error("BlockNotFound: {block}")]
+    BlockNotFound { block: ByteHash },
+
+    #[error("Rejected: block {block} at height {height}: {source}")]
+    Rejected { block: ByteHash, height: u64, source: Box<Error> },
+}
+
+impl ErrorCode for StoreError {
+    fn code(&self) -> u32 {
+        match self {
+            StoreError::NoAttachPoint { .. } => 4001,
+            StoreError::BlockNotFound { .. } => 4002,
+            StoreError::Rejected { .. } => 4003,
+        }
+    }
 }
 
 #[derive(Clone)]
-pub struct Blockchain {
+pub struct Blockchain<L: Ledger = Bank> {
     pub blocks: Vec<Rc<Block>>,
-    bank: Bank,
+    ledger: L,
+    history: HistoryIndex,
     difficulty: usize,
 }
 
-impl Blockchain {
-    pub fn new() -> Blockchain {
+impl<L: Ledger> Default for Blockchain<L> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<L: Ledger> Blockchain<L> {
+    pub fn new() -> Blockchain<L> {
         Blockchain{
             blocks: vec![],
-            bank: Bank::new(),
+            ledger: L::default(),
+            history: HistoryIndex::new(),
             difficulty: 1,
         }
     }
 
-    pub fn append(&mut self, block: &Block) -> anyhow::Result<()> {
-        self.bank.do_block(block)?;
+    pub fn ledger(&self) -> &L {
+        &self.ledger
+    }
+
+    pub fn history(&self) -> &HistoryIndex {
+        &self.history
+    }
+
+    pub fn append(&mut self, block: &Block) -> Result<()> {
+        self.replay(std::slice::from_ref(block))
+    }
+
+    /// Appends the blocks in order, prevalidating all of them at once first.
+    ///
+    /// Stops at the first rejected block, leaving the blocks before it appended.
+    pub fn replay(&mut self, blocks: &[Block]) -> Result<()> {
+        self.ledger.prevalidate(&blocks.iter().collect::<Vec<_>>());
+        blocks.iter().try_for_each(|block| self.apply(block))
+    }
+
+    fn apply(&mut self, block: &Block) -> Result<()> {
+        let height = self.blocks.len() as u64;
+        let movements = self.ledger.movements(block);
+        self.ledger
+            .do_block(block)
+            .map_err(|e| StoreError::Rejected { block: block.hash, height, source: e.into() })?;
+
+        self.history.connect(height, block.hash, movements);
         self.blocks.push(block.clone().into());
 
         Ok(())
     }
 
-    pub fn fork(&self, last_hash: ByteHash) -> anyhow::Result<Blockchain> {
-        let position = 1 + (&self.blocks)
-            .into_iter()
+    pub fn fork(&self, last_hash: ByteHash) -> Result<Blockchain<L>> {
+        let position = 1 + self.blocks
+            .iter()
             .position(|b| b.hash == last_hash)
-            .ok_or(BlockchainError::NoAttachPoint)?;
+            .ok_or(StoreError::BlockNotFound { block: last_hash })?;
 
-        let mut bank = self.bank.clone();
+        let mut ledger = self.ledger.clone();
+        let mut history = self.history.clone();
         let mut blocks = self.blocks.clone();
 
-        for undo_block_idx in self.blocks.len()..position {
-            let undo_block = blocks.swap_remove(undo_block_idx);
-            bank.undo_block(&undo_block)?;
+        while blocks.len() > position {
+            let undo_block = blocks.pop().expect("len checked");
+            let height = blocks.len() as u64;
+            ledger
+                .undo_block(&undo_block)
+                .map_err(|e| StoreError::Rejected { block: undo_block.hash, height, source: e.into() })?;
+
+            history.disconnect(height);
         }
 
         Ok(Blockchain {
             blocks,
-            bank,
+            ledger,
+            history,
             difficulty: self.difficulty,
         })
     }
 
-    pub fn fork_if_needed(&self, block: &Block) -> anyhow::Result<Option<Blockchain>> {
+    pub fn fork_if_needed(&self, block: &Block) -> Result<Option<Blockchain<L>>> {
         if self.into_iter().count() == 0 {
             return Ok(None);
         }
@@ -68,36 +125,64 @@ impl Blockchain {
         let fork_point = self.into_iter().rev().find(|b| b.hash == block.previous_hash);
 
         fork_point
-            .ok_or(BlockchainError::NoAttachPoint.into())
+            .ok_or(StoreError::NoAttachPoint { block: block.hash, previous: block.previous_hash }.into())
             .and_then(|b| if b.hash == last_block.hash { Ok(None) } else { Ok(Some(self.fork(b.hash)?)) })
     }
 
 }
 
-impl<'a> IntoIterator for &'a Blockchain {
+impl<'a, L: Ledger> IntoIterator for &'a Blockchain<L> {
     type Item = &'a Block;
-    type IntoIter = BlockchainIterator<'a>;
+    type IntoIter = BlockchainIterator<'a, L>;
 
     fn into_iter(self) -> Self::IntoIter {
-        BlockchainIterator::new(&self)
+        BlockchainIterator::new(self)
     }
 }
 
-impl fmt::Display for Blockchain {
+impl<L: Ledger> fmt::Display for Blockchain<L> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         for (i, block) in self.blocks.iter().enumerate() {
-            let result = f.write_fmt(format_args!("{} {}\n", i, block));
-            if result.is_err() {
-                return result;
-            }
+            f.write_fmt(format_args!("{} {}\n", i, block))?;
         }
 
-        f.write_fmt(format_args!("\n{}", self.bank))
+        f.write_fmt(format_args!("\n{}", self.ledger))
     }
 }
 
-impl Debug for Blockchain {
+impl<L: Ledger> Debug for Blockchain<L> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("Blockchain").field("blocks", &self.blocks.len()).field("difficulty", &self.difficulty).finish()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rsc_blockdata::block_data::LedgerModel;
+    use crate::rsc_testing::{self, TestWallet};
+
+    #[test]
+    fn fork_then_replay_rebuilds_chain() {
+        let (alice, bob) = (TestWallet::new(), TestWallet::new());
+        let genesis = rsc_testing::genesis(LedgerModel::Account, None);
+        let register_alice = alice.register(genesis.hash);
+        let register_bob = bob.register(register_alice.hash);
+        let transfer = alice.signed(register_bob.hash, 0, alice.transfer(&bob, 30.0, None));
+        let blocks = [genesis, register_alice, register_bob, transfer];
+
+        let mut chain: Blockchain = Blockchain::new();
+        chain.replay(&blocks).unwrap();
+
+        let mut forked = chain.fork(blocks[1].hash).unwrap();
+        assert_eq!(forked.blocks.len(), 2);
+        forked.replay(&blocks[2..]).unwrap();
+        assert_eq!(forked.ledger().serialize_state(), chain.ledger().serialize_state());
+        assert_eq!(forked.history().len(&bob.hash), chain.history().len(&bob.hash));
+
+        // The transfer to bob can't come before bob is registered, so nothing is appended.
+        let mut rejected = chain.fork(blocks[1].hash).unwrap();
+        let error = rejected.replay(&[blocks[3].clone(), blocks[2].clone()]).unwrap_err();
+        assert_eq!((error.code(), rejected.blocks.len()), (4003, 2));
+    }
+}
@@ -1,15 +1,17 @@
 use std::borrow::Borrow;
 
+use crate::rsc_bank::ledger::Ledger;
+
 use super::{chain::Blockchain, block::Block};
 
-pub struct BlockchainIterator<'a> {
+pub struct BlockchainIterator<'a, L: Ledger> {
     idx: usize,
     first_call: bool,
-    chain: &'a Blockchain,
+    chain: &'a Blockchain<L>,
 }
 
-impl<'a> BlockchainIterator<'a> {
-    pub fn new(chain: &'a Blockchain) -> BlockchainIterator {
+impl<'a, L: Ledger> BlockchainIterator<'a, L> {
+    pub fn new(chain: &'a Blockchain<L>) -> BlockchainIterator<'a, L> {
         BlockchainIterator {
             chain,
             idx: 0,
@@ -18,15 +20,15 @@ impl<'a> BlockchainIterator<'a> {
     }
 }
 
-impl<'a> Iterator for BlockchainIterator<'a> {
+impl<'a, L: Ledger> Iterator for BlockchainIterator<'a, L> {
     type Item = &'a Block;
 
     fn next(&mut self) -> Option<Self::Item> {
         if self.chain.blocks.len() <= self.idx {
-            return Option::None;
+            Option::None
         } else {
             self.idx += 1;
-            return Option::Some(&self.chain.blocks[self.idx - 1])
+            Option::Some(&self.chain.blocks[self.idx - 1])
         }
     }
 
@@ -37,15 +39,15 @@ impl<'a> Iterator for BlockchainIterator<'a> {
 
     fn last(self) -> Option<Self::Item>
     {
-        self.chain.blocks.last().and_then(|b| Some(b.borrow()))
+        self.chain.blocks.last().map(|b| b.borrow())
     }
 
     fn nth(&mut self, n: usize) -> Option<Self::Item> {
-        self.chain.blocks.get(n).and_then(|b| Some(b.borrow()))
+        self.chain.blocks.get(n).map(|b| b.borrow())
     }
 }
 
-impl<'a> DoubleEndedIterator for BlockchainIterator<'a> {
+impl<'a, L: Ledger> DoubleEndedIterator for BlockchainIterator<'a, L> {
     fn next_back(&mut self) -> Option<Self::Item> {
         if self.first_call {
             self.idx = self.chain.blocks.len();
@@ -53,10 +55,10 @@ impl<'a> DoubleEndedIterator for BlockchainIterator<'a> {
         }
 
         if self.idx == 0 {
-            return Option::None;
+            Option::None
         } else {
             self.idx -= 1;
-            return Option::Some(&self.chain.blocks[self.idx])
+            Option::Some(&self.chain.blocks[self.idx])
         }
     }
 }
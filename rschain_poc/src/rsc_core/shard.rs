@@ -1,28 +1,91 @@
-use std::{fmt::{Debug, Display}, ptr};
+use std::{fmt::{Debug, Display}, ptr, sync::{Arc, Mutex}};
 
-use super::{chain::Blockchain, block::Block};
+use crate::rsc_bank::{Bank, ledger::Ledger};
+use crate::rsc_error::{Error, ErrorCode, Result};
+use crate::rsc_blockdata::BlockData;
+use crate::rsc_util::{hash::{ByteHash, Hashable}, hasher::HashAlgorithm};
+
+use super::{chain::{Blockchain, StoreError}, block::{self, Block}};
 use thiserror;
 
 #[derive(Debug, thiserror::Error)]
-pub enum ShardError { 
-    #[error("duplicate")]
-    Duplicate,
+pub enum ShardError {
+    #[error("Duplicate: block {block}")]
+    Duplicate { block: ByteHash },
+
+    #[error("Difficulty: block {block} doesn't meet difficulty {difficulty}")]
+    Difficulty { block: ByteHash, difficulty: usize },
+
+    #[error("HashMismatch: block {block} under {algorithm:?}")]
+    HashMismatch { block: ByteHash, algorithm: HashAlgorithm },
 
-    #[error("rejected")]
-    Rejected,
+    #[error("TimestampBeforeParent: block {block} at {timestamp}, parent at {parent}")]
+    TimestampBeforeParent { block: ByteHash, timestamp: u64, parent: u64 },
 
-    #[error("difficulty")]
-    Difficulty,
+    #[error("TimestampInFuture: block {block} at {timestamp}, latest allowed {limit}")]
+    TimestampInFuture { block: ByteHash, timestamp: u64, limit: u64 },
+}
+
+impl ErrorCode for ShardError {
+    fn code(&self) -> u32 {
+        match self {
+            ShardError::Duplicate { .. } => 3001,
+            ShardError::Difficulty { .. } => 3002,
+            ShardError::HashMismatch { .. } => 3003,
+            ShardError::TimestampBeforeParent { .. } => 3004,
+            ShardError::TimestampInFuture { .. } => 3005,
+        }
+    }
 }
 
-pub struct Shard {
+/// How many seconds past the local clock a block timestamp may be, bounding how early time locks can be released.
+pub const MAX_TIMESTAMP_DRIFT: u64 = 10 * 60;
+
+/// Highest difficulty, asking for a hash of zero bytes only.
+pub const MAX_DIFFICULTY: usize = 32;
+
+pub struct Shard<L: Ledger = Bank> {
     lead_idx: Option<usize>,
-    chains: Vec<Blockchain>,
+    chains: Vec<Blockchain<L>>,
     difficulty: usize,
     cleanup_threshold: usize,
+
+    /// Hash function selected by the genesis block.
+    hasher: HashAlgorithm,
+
+    watch: TipWatch,
+}
+
+/// Lead tip of a shard and what blocks extending it must meet.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Tip {
+    /// Last block of the longest chain, none while the shard is empty.
+    pub hash: Option<ByteHash>,
+    pub hasher: HashAlgorithm,
+    pub difficulty: usize,
+}
+
+/// Shared, thread-safe view of a shard's tip, for miners to notice when their work goes stale.
+#[derive(Debug, Clone, Default)]
+pub struct TipWatch(Arc<Mutex<Tip>>);
+
+impl TipWatch {
+    pub fn get(&self) -> Tip {
+        *self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn set(&self, tip: Tip) {
+        *self.0.lock().unwrap_or_else(|e| e.into_inner()) = tip;
+    }
+}
+
+impl<L: Ledger> Default for Shard<L> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
-impl Shard {
+impl<L: Ledger> Shard<L> {
     pub fn new() -> Self {
         let chain = Blockchain::new();
 
@@ -31,72 +94,137 @@ impl Shard {
             lead_idx: None,
             difficulty: 0,
             cleanup_threshold: 3,
+            hasher: HashAlgorithm::default(),
+            watch: TipWatch::default(),
         }
     }
 
-    pub fn check_difficulty(&self, block: &Block) -> bool {
-        for i in 0..self.difficulty {
-            if block.hash[i] != 0u8 {
-                return false;
-            }
+    pub fn tip(&self) -> Tip {
+        Tip {
+            hash: self.lead_idx.and_then(|i| self.chains[i].blocks.last()).map(|b| b.hash),
+            hasher: self.hasher,
+            difficulty: self.difficulty,
         }
+    }
+
+    /// Handle following the tip as blocks are pushed, which can be moved to other threads.
+    pub fn watch(&self) -> TipWatch {
+        self.watch.clone()
+    }
+
+    /// Hash function the block is hashed with, which a genesis block selects for itself.
+    pub fn hash_algorithm_for(&self, block: &Block) -> HashAlgorithm {
+        block.hash_algorithm(self.hasher)
+    }
+
+    pub fn difficulty(&self) -> usize {
+        self.difficulty
+    }
+
+    /// Number of leading zero bytes block hashes need to be accepted, capped at `MAX_DIFFICULTY`.
+    pub fn set_difficulty(&mut self, difficulty: usize) {
+        self.difficulty = difficulty.min(MAX_DIFFICULTY);
+        self.watch.set(self.tip());
+    }
 
-        true
+    pub fn check_difficulty(&self, block: &Block) -> bool {
+        block.meets_difficulty(self.difficulty)
     }
 
-    pub fn push(&mut self, block: Block) -> anyhow::Result<()> {
-        let is_duplicate = (&self.chains)
-            .into_iter()
-            .any(|chain| chain.into_iter()
-                .any(|b| b.hash == (&block).hash));
+    pub fn push(&mut self, block: Block) -> Result<()> {
+        if self.contains(&block.hash) {
+            return Err(ShardError::Duplicate { block: block.hash }.into());
+        }
 
-        if is_duplicate {
-            return Err(ShardError::Duplicate.into());
+        let algorithm = self.hash_algorithm_for(&block);
+        if block.hash != block.hash_with(algorithm) {
+            return Err(ShardError::HashMismatch { block: block.hash, algorithm }.into());
         }
 
         if !self.check_difficulty(&block) {
-            return Err(ShardError::Difficulty.into());
+            return Err(ShardError::Difficulty { block: block.hash, difficulty: self.difficulty }.into());
         }
 
+        self.check_timestamp(&block)?;
+
+        let is_empty = self.chains.iter().all(|c| c.into_iter().next().is_none());
+        let genesis_hasher = match (is_empty, &block.data) {
+            (true, BlockData::Genesis(data)) => Some(data.hasher),
+            _ => None,
+        };
+
         self.push_impl(block)?;
+
+        if let Some(hasher) = genesis_hasher {
+            self.hasher = hasher;
+        }
+
         self.update_longest_chain_idx();
         self.cleanup();
+        self.watch.set(self.tip());
 
-        return Ok(());
+        Ok(())
     }
 
-    fn push_impl(&mut self, block: Block) -> anyhow::Result<()> {
-        let mut new_chains = Vec::<Blockchain>::new();
-        let result: anyhow::Result<()>;
+    fn contains(&self, hash: &ByteHash) -> bool {
+        self.chains.iter().any(|chain| chain.into_iter().any(|b| b.hash == *hash))
+    }
 
-        {
-            result = self.chains.iter_mut().map(|chain: &mut Blockchain| -> anyhow::Result<()> {
-                if chain.into_iter().any(|b| b.hash == (&block).hash) {
-                    Err(ShardError::Duplicate)?
-                }
+    /// Timestamps may not go back from the parent's, nor run ahead of the local clock by more than the allowed drift.
+    fn check_timestamp(&self, block: &Block) -> Result<()> {
+        let limit = block::now() + MAX_TIMESTAMP_DRIFT;
+        if block.timestamp > limit {
+            Err(ShardError::TimestampInFuture { block: block.hash, timestamp: block.timestamp, limit })?;
+        }
+
+        let parent = self.chains
+            .iter()
+            .flat_map(|chain| chain.into_iter())
+            .find(|b| b.hash == block.previous_hash);
+
+        if let Some(parent) = parent.filter(|p| block.timestamp < p.timestamp) {
+            Err(ShardError::TimestampBeforeParent { block: block.hash, timestamp: block.timestamp, parent: parent.timestamp })?;
+        }
 
-                chain.fork_if_needed(&block).and_then(|r| {
-                    match r {
-                        None => chain.append(&block),
-                        Some(mut new_chain) => { new_chain.append(&block)?; new_chains.push(new_chain); Ok(()) }
-                    }
-                })
-            }).fold(Ok(()), |a, r| {
-                if r.is_err() {
-                    dbg!(r.err());
+        Ok(())
+    }
+
+    /// Appends the block to every chain it extends, failing if none accepts it.
+    ///
+    /// Chains the block doesn't attach to are expected to refuse it, so the error reported is the first
+    /// other one, such as the ledger rejecting the block.
+    fn push_impl(&mut self, block: Block) -> Result<()> {
+        let mut new_chains = Vec::<Blockchain<L>>::new();
+        let mut errors = Vec::<Error>::new();
+
+        for chain in self.chains.iter_mut() {
+            let result = chain.fork_if_needed(&block).and_then(|r| {
+                match r {
+                    None => chain.append(&block),
+                    Some(mut new_chain) => { new_chain.append(&block)?; new_chains.push(new_chain); Ok(()) }
                 }
+            });
+
+            if let Err(e) = result {
+                errors.push(e);
+            }
+        }
+
+        if errors.len() == self.chains.len() {
+            let (unattached, rejected): (Vec<Error>, Vec<Error>) = errors
+                .into_iter()
+                .partition(|e| matches!(e, Error::Store(StoreError::NoAttachPoint { .. })));
 
-                a
-            })
+            return Err(rejected.into_iter().chain(unattached).next().expect("shard has a chain"));
         }
 
         self.chains.append(&mut new_chains);
-        return result;
+        Ok(())
     }
 
     fn update_longest_chain_idx(&mut self) {
-        let chain = (&self.chains).into_iter().max_by_key(|c| c.into_iter().count());
-        self.lead_idx = chain.and_then(|c| (&self.chains).into_iter().position(|a| ptr::eq(a, c)));
+        let chain = self.chains.iter().max_by_key(|c| c.into_iter().count());
+        self.lead_idx = chain.and_then(|c| self.chains.iter().position(|a| ptr::eq(a, c)));
     }
 
     fn cleanup(&mut self) {
@@ -108,8 +236,8 @@ impl Shard {
         let leader = leader.expect("precheck");
 
         let mut removed_keys = Vec::<usize>::new();
-        for (i, chain) in (&self.chains).into_iter().enumerate() {
-            let diff = leader.into_iter().count().checked_sub(chain.into_iter().count()).unwrap_or(0);
+        for (i, chain) in self.chains.iter().enumerate() {
+            let diff = leader.into_iter().count().saturating_sub(chain.into_iter().count());
             if diff > self.cleanup_threshold {
                 removed_keys.push(i);
             }
@@ -118,16 +246,19 @@ impl Shard {
         for key in removed_keys.into_iter().rev() {
             self.chains.remove(key);
         }
+
+        // Removing chains shifts the leader's index.
+        self.update_longest_chain_idx();
     }
 }
 
-impl Debug for Shard {
+impl<L: Ledger> Debug for Shard<L> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("Shard").field("longest_chain", &self.lead_idx).field("chains", &self.chains.len()).finish()
     }
 }
 
-impl Display for Shard {
+impl<L: Ledger> Display for Shard<L> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self.lead_idx {
             Some(idx) => f.write_fmt(format_args!("[Chain {} out of {}]\n{}", idx + 1, self.chains.len(), self.chains[idx])),
@@ -137,3 +268,136 @@ impl Display for Shard {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rsc_bank::{WALLET_GRANT, currency::NATIVE_CURRENCY};
+    use crate::rsc_blockdata::block_data::{LedgerModel, Lock, MonetaryPolicyConfig};
+    use crate::rsc_error::ErrorCode;
+    use crate::rsc_testing::{self, TestWallet};
+
+    fn block(previous_hash: ByteHash, timestamp: u64) -> Block {
+        let mut block = Block::new(previous_hash, BlockData::Empty);
+        block.timestamp = timestamp;
+        block.update_nonce(0, HashAlgorithm::default());
+        block
+    }
+
+    #[test]
+    fn rejects_timestamp_ahead_of_clock() {
+        let mut shard: Shard = Shard::new();
+        let error = shard.push(block(ByteHash::new(), block::now() + 2 * MAX_TIMESTAMP_DRIFT)).unwrap_err();
+
+        assert_eq!(error.code(), 3005);
+        assert_eq!(shard.tip().hash, None);
+    }
+
+    #[test]
+    fn rejects_timestamp_before_parent() {
+        let mut shard: Shard = Shard::new();
+        let genesis = block(ByteHash::new(), block::now());
+        shard.push(genesis.clone()).unwrap();
+
+        let error = shard.push(block(genesis.hash, genesis.timestamp - 1)).unwrap_err();
+        assert_eq!(error.code(), 3004);
+
+        let next = block(genesis.hash, genesis.timestamp);
+        shard.push(next.clone()).unwrap();
+        assert_eq!(shard.tip().hash, Some(next.hash));
+    }
+
+    #[test]
+    fn rejected_genesis_leaves_hash_function_unselected() {
+        let mut shard: Shard = Shard::new();
+        let policy = MonetaryPolicyConfig::Faucet { grant: 1.0, subsidy: 5.0 };
+        let mut data = rsc_testing::genesis_data(LedgerModel::Account, Some(policy));
+        data.hasher = HashAlgorithm::Blake3;
+
+        // The ledger rejects it, as the subsidy goes to a wallet which does not exist.
+        let mut rejected = Block::new(ByteHash::new(), BlockData::Genesis(data));
+        rejected.coinbase = Some(TestWallet::new().hash);
+        rejected.update_nonce(0, HashAlgorithm::Blake3);
+        let error = shard.push(rejected).unwrap_err();
+        assert_eq!((error.code(), error.root().code()), (4003, 2001));
+        assert_eq!((shard.tip().hash, shard.hasher), (None, HashAlgorithm::default()));
+
+        let genesis = rsc_testing::genesis(LedgerModel::Account, None);
+        let next = rsc_testing::block(genesis.hash, BlockData::Empty);
+        shard.push(genesis).unwrap();
+        shard.push(next.clone()).unwrap();
+        assert_eq!(shard.tip().hash, Some(next.hash));
+    }
+
+    #[test]
+    fn difficulty_capped_at_hash_length() {
+        let mut shard: Shard = Shard::new();
+        shard.set_difficulty(usize::MAX);
+        assert_eq!((shard.difficulty(), shard.tip().difficulty), (MAX_DIFFICULTY, MAX_DIFFICULTY));
+
+        let error = shard.push(block(ByteHash::new(), block::now())).unwrap_err();
+        assert_eq!(error.code(), 3002);
+    }
+
+    #[test]
+    fn push_reports_ledger_rejection() {
+        let (alice, bob) = (TestWallet::new(), TestWallet::new());
+        let mut shard: Shard = Shard::new();
+
+        let genesis = rsc_testing::genesis(LedgerModel::Account, None);
+        let register_alice = alice.register(genesis.hash);
+        let register_bob = bob.register(register_alice.hash);
+        for block in [&genesis, &register_alice, &register_bob] {
+            shard.push(block.clone()).unwrap();
+        }
+
+        let overspend = alice.signed(register_bob.hash, 0, alice.transfer(&bob, WALLET_GRANT + 1.0, None));
+        let error = shard.push(overspend).unwrap_err();
+        assert_eq!((error.code(), error.root().code()), (4003, 2004));
+        assert_eq!(shard.tip().hash, Some(register_bob.hash));
+
+        let unattached = rsc_testing::block(ByteHash::new(), BlockData::Empty);
+        assert_eq!(shard.push(unattached).unwrap_err().code(), 4001);
+    }
+
+    /// Spendable and locked funds of the wallet on the chain ending with given block.
+    fn funds(shard: &Shard, tip: ByteHash, wallet: &TestWallet) -> (f64, f64) {
+        let chain = shard.chains.iter().find(|c| c.blocks.last().is_some_and(|b| b.hash == tip)).expect("chain ending at tip");
+        let wallet = &chain.ledger().wallets[&wallet.hash];
+
+        (wallet.accounts[&NATIVE_CURRENCY], wallet.locked.iter().map(|l| l.amount).sum())
+    }
+
+    #[test]
+    fn reorg_across_unlock_height_relocks() {
+        let (alice, bob) = (TestWallet::new(), TestWallet::new());
+        let mut shard: Shard = Shard::new();
+
+        let genesis = rsc_testing::genesis(LedgerModel::Account, None);
+        let register_alice = alice.register(genesis.hash);
+        let register_bob = bob.register(register_alice.hash);
+        let transfer = alice.signed(register_bob.hash, 0, alice.transfer(&bob, 40.0, Some(Lock::Height(5))));
+        let a4 = rsc_testing::block(transfer.hash, BlockData::Empty);
+        let a5 = rsc_testing::block(a4.hash, BlockData::Empty);
+
+        for block in [&genesis, &register_alice, &register_bob, &transfer, &a4, &a5] {
+            shard.push(block.clone()).unwrap();
+        }
+
+        assert_eq!(funds(&shard, a5.hash, &bob), (WALLET_GRANT + 40.0, 0.0));
+
+        // A competing branch off the transfer has to undo the release until it reaches the unlock height itself.
+        let b4 = rsc_testing::block_at(transfer.hash, BlockData::Empty, a4.timestamp + 1);
+        shard.push(b4.clone()).unwrap();
+        assert_eq!(funds(&shard, b4.hash, &bob), (WALLET_GRANT, 40.0));
+        assert_eq!(funds(&shard, a5.hash, &bob), (WALLET_GRANT + 40.0, 0.0));
+
+        let b5 = rsc_testing::block_at(b4.hash, BlockData::Empty, b4.timestamp);
+        let b6 = rsc_testing::block_at(b5.hash, BlockData::Empty, b4.timestamp);
+        shard.push(b5).unwrap();
+        shard.push(b6.clone()).unwrap();
+
+        assert_eq!(shard.tip().hash, Some(b6.hash));
+        assert_eq!(funds(&shard, b6.hash, &bob), (WALLET_GRANT + 40.0, 0.0));
+    }
+}
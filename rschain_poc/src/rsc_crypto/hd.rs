@@ -0,0 +1,297 @@
+use std::{fmt::Display, str::FromStr};
+
+use bip39::Mnemonic;
+use hmac::{Hmac, Mac};
+use k256::{elliptic_curve::PrimeField, FieldBytes, Scalar};
+use sha2::Sha512;
+use zeroize::Zeroizing;
+
+use crate::rsc_error::{Result, ValidationError};
+use super::{keystore::WalletKey, signature::Scheme};
+
+/// Offset of hardened child indexes, written with a `'` or `h` suffix in paths.
+pub const HARDENED: u32 = 0x8000_0000;
+
+/// BIP-44 purpose and the SLIP-44 coin type shared by all test networks.
+pub const WALLET_PURPOSE: u32 = 44;
+pub const COIN_TYPE: u32 = 1;
+
+/// New random 24 word BIP-39 mnemonic phrase, the one backup all wallet keys derive from.
+pub fn generate_mnemonic() -> String {
+    Mnemonic::from_entropy(&rand::random::<[u8; 32]>())
+        .expect("32 bytes of entropy")
+        .to_string()
+}
+
+/// BIP-39 seed of a mnemonic phrase, with the checksum of the phrase verified.
+pub fn mnemonic_to_seed(phrase: &str, passphrase: &str) -> Result<Zeroizing<[u8; 64]>> {
+    let mnemonic = Mnemonic::parse(phrase).map_err(|_| ValidationError::MnemonicInvalid)?;
+
+    Ok(Zeroizing::new(mnemonic.to_seed(passphrase)))
+}
+
+/// Child indexes from the master key down, parsed from and printed as `m/44'/1'/0'`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DerivationPath(Vec<u32>);
+
+impl DerivationPath {
+    /// Path of the wallet at given index of an account, hardened at every level so it is valid for every scheme.
+    pub fn wallet(account: u32, index: u32) -> DerivationPath {
+        DerivationPath(vec![WALLET_PURPOSE | HARDENED, COIN_TYPE | HARDENED, account | HARDENED, HARDENED, index | HARDENED])
+    }
+
+    pub fn indexes(&self) -> &[u32] {
+        &self.0
+    }
+}
+
+impl FromStr for DerivationPath {
+    type Err = ValidationError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let invalid = || ValidationError::DerivationPathInvalid { path: s.to_string() };
+        let mut parts = s.split('/');
+        if parts.next() != Some("m") {
+            return Err(invalid());
+        }
+
+        parts
+            .map(|part| {
+                let (number, hardened) = match part.strip_suffix(['\'', 'h']) {
+                    Some(number) => (number, HARDENED),
+                    None => (part, 0),
+                };
+
+                number.parse::<u32>().ok().filter(|i| i & HARDENED == 0).map(|i| i | hardened).ok_or_else(invalid)
+            })
+            .collect::<std::result::Result<Vec<u32>, _>>()
+            .map(DerivationPath)
+    }
+}
+
+impl Display for DerivationPath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("m")?;
+        for index in &self.0 {
+            match index & HARDENED {
+                0 => f.write_fmt(format_args!("/{}", index))?,
+                _ => f.write_fmt(format_args!("/{}'", index & !HARDENED))?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Private key with the chain code its children are derived with.
+///
+/// Secp256k1 keys follow BIP-32, Ed25519 keys follow SLIP-0010 and only have hardened children.
+pub struct ExtendedKey {
+    scheme: Scheme,
+    secret: Zeroizing<[u8; 32]>,
+    chain_code: [u8; 32],
+}
+
+impl ExtendedKey {
+    pub fn master(scheme: Scheme, seed: &[u8]) -> Result<ExtendedKey> {
+        let curve_key: &[u8] = match scheme {
+            Scheme::Ed25519 => b"ed25519 seed",
+            Scheme::Secp256k1 => b"Bitcoin seed",
+            Scheme::Rsa => Err(ValidationError::DerivationUnsupported { scheme })?,
+        };
+
+        let (secret, chain_code) = hmac_split(curve_key, &[seed]);
+        if scheme == Scheme::Secp256k1 && !is_valid_scalar(&secret) {
+            Err(ValidationError::DerivationPathInvalid { path: "m".to_string() })?;
+        }
+
+        Ok(ExtendedKey { scheme, secret, chain_code })
+    }
+
+    pub fn from_mnemonic(scheme: Scheme, phrase: &str, passphrase: &str) -> Result<ExtendedKey> {
+        Self::master(scheme, mnemonic_to_seed(phrase, passphrase)?.as_ref())
+    }
+
+    pub fn derive(&self, path: &DerivationPath) -> Result<ExtendedKey> {
+        let mut key = ExtendedKey { scheme: self.scheme, secret: self.secret.clone(), chain_code: self.chain_code };
+        for index in &path.0 {
+            key = key.child(*index, path)?;
+        }
+
+        Ok(key)
+    }
+
+    /// Child at given index, the path only used to report an invalid derivation.
+    fn child(&self, index: u32, path: &DerivationPath) -> Result<ExtendedKey> {
+        let invalid = || ValidationError::DerivationPathInvalid { path: path.to_string() };
+        let index_bytes = index.to_be_bytes();
+
+        match self.scheme {
+            Scheme::Ed25519 => {
+                if index & HARDENED == 0 {
+                    Err(invalid())?;
+                }
+
+                let (secret, chain_code) = hmac_split(&self.chain_code, &[&[0], self.secret.as_ref(), &index_bytes]);
+                Ok(ExtendedKey { scheme: self.scheme, secret, chain_code })
+            },
+            Scheme::Secp256k1 => {
+                let parent = scalar(&self.secret).ok_or_else(invalid)?;
+                let (tweak, chain_code) = match index & HARDENED {
+                    0 => hmac_split(&self.chain_code, &[self.public_key()?.as_slice(), &index_bytes]),
+                    _ => hmac_split(&self.chain_code, &[&[0], self.secret.as_ref(), &index_bytes]),
+                };
+
+                let child = scalar(&tweak).ok_or_else(invalid)? + parent;
+                if bool::from(child.is_zero()) {
+                    Err(invalid())?;
+                }
+
+                Ok(ExtendedKey { scheme: self.scheme, secret: Zeroizing::new(child.to_bytes().into()), chain_code })
+            },
+            Scheme::Rsa => Err(ValidationError::DerivationUnsupported { scheme: self.scheme }.into()),
+        }
+    }
+
+    pub fn scheme(&self) -> Scheme {
+        self.scheme
+    }
+
+    pub fn chain_code(&self) -> [u8; 32] {
+        self.chain_code
+    }
+
+    pub fn wallet_key(&self) -> Result<WalletKey> {
+        Ok(match self.scheme {
+            Scheme::Ed25519 => WalletKey::Ed25519(ed25519_dalek::SigningKey::from_bytes(&self.secret)),
+            Scheme::Secp256k1 => WalletKey::Secp256k1(k256::ecdsa::SigningKey::from_slice(self.secret.as_ref()).map_err(|_| ValidationError::InvalidPublicKey)?),
+            Scheme::Rsa => Err(ValidationError::DerivationUnsupported { scheme: self.scheme })?,
+        })
+    }
+
+    /// Public key bytes as BIP-32 and SLIP-0010 serialize them: compressed SEC1, or the Ed25519 key behind a zero byte.
+    pub fn public_key(&self) -> Result<Vec<u8>> {
+        let public_key = hex::decode(self.wallet_key()?.public_key()?)?;
+
+        Ok(match self.scheme {
+            Scheme::Ed25519 => [&[0], public_key.as_slice()].concat(),
+            _ => public_key,
+        })
+    }
+}
+
+fn hmac_split(key: &[u8], data: &[&[u8]]) -> (Zeroizing<[u8; 32]>, [u8; 32]) {
+    let mut mac = Hmac::<Sha512>::new_from_slice(key).expect("hmac accepts any key length");
+    for part in data {
+        mac.update(part);
+    }
+
+    let output = Zeroizing::new(<[u8; 64]>::from(mac.finalize().into_bytes()));
+    let (left, right) = output.split_at(32);
+
+    (Zeroizing::new(left.try_into().expect("half of 64")), right.try_into().expect("half of 64"))
+}
+
+/// Secp256k1 scalar of the bytes, none if they are not below the curve order.
+fn scalar(bytes: &[u8; 32]) -> Option<Scalar> {
+    Option::from(Scalar::from_repr(FieldBytes::from(*bytes)))
+}
+
+fn is_valid_scalar(bytes: &[u8; 32]) -> bool {
+    scalar(bytes).is_some_and(|s| !bool::from(s.is_zero()))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rsc_error::ErrorCode;
+
+    /// Seed shared by the BIP-32 and SLIP-0010 test vector 1.
+    const VECTOR_SEED: &str = "000102030405060708090a0b0c0d0e0f";
+
+    /// (scheme, path, chain code, private key, public key) of the BIP-32 and SLIP-0010 test vector 1.
+    const DERIVATION_VECTORS: &[(Scheme, &str, &str, &str, &str)] = &[
+        (Scheme::Secp256k1, "m",
+            "873dff81c02f525623fd1fe5167eac3a55a049de3d314bb42ee227ffed37d508",
+            "e8f32e723decf4051aefac8e2c93c9c5b214313817cdb01a1494b917c8436b35",
+            "0339a36013301597daef41fbe593a02cc513d0b55527ec2df1050e2e8ff49c85c2"),
+        (Scheme::Secp256k1, "m/0'",
+            "47fdacbd0f1097043b78c63c20c34ef4ed9a111d980047ad16282c7ae6236141",
+            "edb2e14f9ee77d26dd93b4ecede8d16ed408ce149b6cd80b0715a2d911a0afea",
+            "035a784662a4a20a65bf6aab9ae98a6c068a81c52e4b032c0fb5400c706cfccc56"),
+        (Scheme::Secp256k1, "m/0'/1",
+            "2a7857631386ba23dacac34180dd1983734e444fdbf774041578e9b6adb37c19",
+            "3c6cb8d0f6a264c91ea8b5030fadaa8e538b020f0a387421a12de9319dc93368",
+            "03501e454bf00751f24b1b489aa925215d66af2234e3891c3b21a52bedb3cd711c"),
+        (Scheme::Secp256k1, "m/0'/1/2'",
+            "04466b9cc8e161e966409ca52986c584f07e9dc81f735db683c3ff6ec7b1503f",
+            "cbce0d719ecf7431d88e6a89fa1483e02e35092af60c042b1df2ff59fa424dca",
+            "0357bfe1e341d01c69fe5654309956cbea516822fba8a601743a012a7896ee8dc2"),
+        (Scheme::Secp256k1, "m/0'/1/2'/2",
+            "cfb71883f01676f587d023cc53a35bc7f88f724b1f8c2892ac1275ac822a3edd",
+            "0f479245fb19a38a1954c5c7c0ebab2f9bdfd96a17563ef28a6a4b1a2a764ef4",
+            "02e8445082a72f29b75ca48748a914df60622a609cacfce8ed0e35804560741d29"),
+        (Scheme::Secp256k1, "m/0'/1/2'/2/1000000000",
+            "c783e67b921d2beb8f6b389cc646d7263b4145701dadd2161548a8b078e65e9e",
+            "471b76e389e528d6de6d816857e012c5455051cad6660850e58372a6c3e6e7c8",
+            "022a471424da5e657499d1ff51cb43c47481a03b1e77f951fe64cec9f5a48f7011"),
+        (Scheme::Ed25519, "m",
+            "90046a93de5380a72b5e45010748567d5ea02bbf6522f979e05c0d8d8ca9fffb",
+            "2b4be7f19ee27bbf30c667b642d5f4aa69fd169872f8fc3059c08ebae2eb19e7",
+            "00a4b2856bfec510abab89753fac1ac0e1112364e7d250545963f135f2a33188ed"),
+        (Scheme::Ed25519, "m/0'",
+            "8b59aa11380b624e81507a27fedda59fea6d0b779a778918a2fd3590e16e9c69",
+            "68e0fe46dfb67e368c75379acec591dad19df3cde26e63b93a8e704f1dade7a3",
+            "008c8a13df77a28f3445213a0f432fde644acaa215fc72dcdf300d5efaa85d350c"),
+        (Scheme::Ed25519, "m/0'/1'",
+            "a320425f77d1b5c2505a6b1b27382b37368ee640e3557c315416801243552f14",
+            "b1d0bad404bf35da785a64ca1ac54b2617211d2777696fbffaf208f746ae84f2",
+            "001932a5270f335bed617d5b935c80aedb1a35bd9fc1e31acafd5372c30f5c1187"),
+        (Scheme::Ed25519, "m/0'/1'/2'",
+            "2e69929e00b5ab250f49c3fb1c12f252de4fed2c1db88387094a0f8c4c9ccd6c",
+            "92a5b23c0b8a99e37d07df3fb9966917f5d06e02ddbd909c7e184371463e9fc9",
+            "00ae98736566d30ed0e9d2f4486a64bc95740d89c7db33f52121f8ea8f76ff0fc1"),
+        (Scheme::Ed25519, "m/0'/1'/2'/2'",
+            "8f6d87f93d750e0efccda017d662a1b31a266e4a6f5993b15f5c1f07f74dd5cc",
+            "30d1dc7e5fc04c31219ab25a27ae00b50f6fd66622f6e9c913253d6511d1e662",
+            "008abae2d66361c879b900d204ad2cc4984fa2aa344dd7ddc46007329ac76c429c"),
+        (Scheme::Ed25519, "m/0'/1'/2'/2'/1000000000'",
+            "68789923a0cac2cd5a29172a475fe9e0fb14cd6adb5ad98a3fa70333e7afa230",
+            "8f94d394a8e8fd6b1bc2f3f49f5c47e385281d5c17e65324b0f62483e37e8793",
+            "003c24da049451555d51a7014a37337aa4e12d41e485abccfa46b47dfb2af54b7a"),
+    ];
+
+    /// (mnemonic, seed with passphrase "TREZOR") of the BIP-39 reference test vectors.
+    const MNEMONIC_VECTORS: &[(&str, &str)] = &[
+        ("abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about",
+            "c55257c360c07c72029aebc1b53c05ed0362ada38ead3e3e9efa3708e53495531f09a6987599d18264c1e1c92f2cf141630c7a3c4ab7c81b2f001698e7463b04"),
+    ];
+
+    #[test]
+    fn mnemonic_vectors() {
+        for (phrase, seed) in MNEMONIC_VECTORS {
+            assert_eq!(hex::encode(mnemonic_to_seed(phrase, "TREZOR").unwrap().as_ref()), *seed, "{}", phrase);
+        }
+    }
+
+    #[test]
+    fn derivation_vectors() {
+        let seed = hex::decode(VECTOR_SEED).unwrap();
+        for (scheme, path, chain_code, secret, public_key) in DERIVATION_VECTORS {
+            let key = ExtendedKey::master(*scheme, &seed).unwrap().derive(&path.parse().unwrap()).unwrap();
+
+            assert_eq!(hex::encode(key.chain_code), *chain_code, "{:?} {}", scheme, path);
+            assert_eq!(hex::encode(key.secret.as_ref()), *secret, "{:?} {}", scheme, path);
+            assert_eq!(hex::encode(key.public_key().unwrap()), *public_key, "{:?} {}", scheme, path);
+        }
+    }
+
+    #[test]
+    fn ed25519_rejects_normal_derivation() {
+        let key = ExtendedKey::master(Scheme::Ed25519, &hex::decode(VECTOR_SEED).unwrap()).unwrap();
+        let error = key.derive(&"m/0'/1".parse().unwrap()).err().expect("normal child of an Ed25519 key");
+
+        assert_eq!(error.code(), 1014);
+    }
+}
@@ -0,0 +1,399 @@
+use std::{collections::HashMap, fs, io::Write, path::{Path, PathBuf}, time::{Duration, Instant}};
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::{aead::{Aead, Payload}, KeyInit, XChaCha20Poly1305, XNonce};
+use k256::ecdsa;
+use openssl::pkey::{PKey, Private};
+use serde::{Serialize, Deserialize};
+use zeroize::Zeroizing;
+
+use crate::{rsc_util::{hash::{ByteHash, Hashable}, hasher::HashAlgorithm}, rsc_blockdata::block_data::WalletData, rsc_error::{ErrorCode, Result}};
+use super::signature::{Ed25519, RsaPkcs1, Scheme, Secp256k1, SignatureScheme};
+
+const KEY_FILE_EXTENSION: &str = "json";
+const SALT_LEN: usize = 16;
+
+#[derive(thiserror::Error, Debug)]
+pub enum KeystoreError {
+    #[error("KeyNotFound: {wallet}")]
+    KeyNotFound { wallet: ByteHash },
+
+    #[error("KeyDuplicate: {wallet}")]
+    KeyDuplicate { wallet: ByteHash },
+
+    #[error("PasswordInvalid: {wallet}")]
+    PasswordInvalid { wallet: ByteHash },
+
+    #[error("KeyLocked: {wallet}")]
+    KeyLocked { wallet: ByteHash },
+
+    #[error("KeyFileInvalid: {wallet}")]
+    KeyFileInvalid { wallet: String },
+
+    #[error("KeyFileMalformed")]
+    KeyFileMalformed,
+
+    #[error("Io: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+impl ErrorCode for KeystoreError {
+    fn code(&self) -> u32 {
+        match self {
+            KeystoreError::KeyNotFound { .. } => 6001,
+            KeystoreError::KeyDuplicate { .. } => 6002,
+            KeystoreError::PasswordInvalid { .. } => 6003,
+            KeystoreError::KeyLocked { .. } => 6004,
+            KeystoreError::KeyFileInvalid { .. } => 6005,
+            KeystoreError::KeyFileMalformed => 6006,
+            KeystoreError::Io(_) => 6007,
+        }
+    }
+}
+
+/// Private key of a wallet, in any of the supported signature schemes.
+pub enum WalletKey {
+    Ed25519(ed25519_dalek::SigningKey),
+    Rsa(PKey<Private>),
+    Secp256k1(ecdsa::SigningKey),
+}
+
+impl WalletKey {
+    pub fn generate(scheme: Scheme) -> Result<WalletKey> {
+        Ok(match scheme {
+            Scheme::Ed25519 => WalletKey::Ed25519(Ed25519::generate()?),
+            Scheme::Rsa => WalletKey::Rsa(RsaPkcs1::generate()?),
+            Scheme::Secp256k1 => WalletKey::Secp256k1(Secp256k1::generate()?),
+        })
+    }
+
+    pub fn scheme(&self) -> Scheme {
+        match self {
+            WalletKey::Ed25519(_) => Scheme::Ed25519,
+            WalletKey::Rsa(_) => Scheme::Rsa,
+            WalletKey::Secp256k1(_) => Scheme::Secp256k1,
+        }
+    }
+
+    pub fn public_key(&self) -> Result<String> {
+        match self {
+            WalletKey::Ed25519(key) => Ed25519::public_key(key),
+            WalletKey::Rsa(key) => RsaPkcs1::public_key(key),
+            WalletKey::Secp256k1(key) => Secp256k1::public_key(key),
+        }
+    }
+
+    pub fn sign(&self, data: &[u8]) -> Result<Vec<u8>> {
+        match self {
+            WalletKey::Ed25519(key) => Ed25519::sign(key, data),
+            WalletKey::Rsa(key) => RsaPkcs1::sign(key, data),
+            WalletKey::Secp256k1(key) => Secp256k1::sign(key, data),
+        }
+    }
+
+    /// Wallet block registering this key.
+    pub fn wallet_data(&self) -> Result<WalletData> {
+        Ok(WalletData { pubkey: self.public_key()?, scheme: self.scheme() })
+    }
+
+    fn to_secret(&self) -> Result<Zeroizing<Vec<u8>>> {
+        Ok(Zeroizing::new(match self {
+            WalletKey::Ed25519(key) => key.to_bytes().to_vec(),
+            WalletKey::Rsa(key) => key.private_key_to_der()?,
+            WalletKey::Secp256k1(key) => key.to_bytes().to_vec(),
+        }))
+    }
+
+    fn from_secret(scheme: Scheme, secret: &[u8]) -> Option<WalletKey> {
+        Some(match scheme {
+            Scheme::Ed25519 => WalletKey::Ed25519(ed25519_dalek::SigningKey::from_bytes(secret.try_into().ok()?)),
+            Scheme::Rsa => WalletKey::Rsa(PKey::private_key_from_der(secret).ok()?),
+            Scheme::Secp256k1 => WalletKey::Secp256k1(ecdsa::SigningKey::from_slice(secret).ok()?),
+        })
+    }
+}
+
+/// Argon2id cost parameters a key file was encrypted with.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct KdfParams {
+    salt: String,
+    memory: u32,
+    iterations: u32,
+    parallelism: u32,
+}
+
+impl KdfParams {
+    fn derive(&self, password: &str) -> Option<Zeroizing<[u8; 32]>> {
+        let params = Params::new(self.memory, self.iterations, self.parallelism, Some(32)).ok()?;
+        let salt = hex::decode(&self.salt).ok()?;
+
+        let mut key = Zeroizing::new([0u8; 32]);
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password_into(password.as_bytes(), &salt, key.as_mut())
+            .ok()?;
+
+        Some(key)
+    }
+}
+
+impl Default for KdfParams {
+    fn default() -> Self {
+        KdfParams {
+            salt: hex::encode(rand::random::<[u8; SALT_LEN]>()),
+            memory: Params::DEFAULT_M_COST,
+            iterations: Params::DEFAULT_T_COST,
+            parallelism: Params::DEFAULT_P_COST,
+        }
+    }
+}
+
+/// On-disk form of a key, the secret encrypted with XChaCha20-Poly1305 under a key derived from the password.
+///
+/// The wallet hash is authenticated along with the ciphertext, so a key file cannot be passed off as another wallet's.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct KeyFile {
+    wallet: String,
+
+    /// Hash function the wallet hash was derived with.
+    #[serde(default)]
+    hasher: HashAlgorithm,
+
+    scheme: Scheme,
+    pubkey: String,
+    kdf: KdfParams,
+    nonce: String,
+    ciphertext: String,
+}
+
+impl KeyFile {
+    fn seal(key: &WalletKey, password: &str, hasher: HashAlgorithm) -> Result<KeyFile> {
+        let wallet = key.wallet_data()?.hash_with(hasher);
+        let kdf = KdfParams::default();
+        let cipher_key = kdf.derive(password).expect("default kdf params");
+        let nonce = rand::random::<[u8; 24]>();
+
+        let ciphertext = XChaCha20Poly1305::new(cipher_key.as_ref().into())
+            .encrypt(XNonce::from_slice(&nonce), Payload { msg: &key.to_secret()?, aad: &wallet.to_ne_bytes() })
+            .expect("encryption of in-memory buffer");
+
+        Ok(KeyFile {
+            wallet: wallet.into(),
+            hasher,
+            scheme: key.scheme(),
+            pubkey: key.public_key()?,
+            kdf,
+            nonce: hex::encode(nonce),
+            ciphertext: hex::encode(ciphertext),
+        })
+    }
+
+    fn open(&self, password: &str) -> Result<WalletKey> {
+        let wallet = self.wallet()?;
+        let invalid = || KeystoreError::KeyFileInvalid { wallet: self.wallet.clone() };
+
+        let cipher_key = self.kdf.derive(password).ok_or_else(invalid)?;
+        let nonce: [u8; 24] = hex::decode(&self.nonce)?.try_into().map_err(|_| invalid())?;
+        let ciphertext = hex::decode(&self.ciphertext)?;
+
+        let secret = XChaCha20Poly1305::new(cipher_key.as_ref().into())
+            .decrypt(XNonce::from_slice(&nonce), Payload { msg: &ciphertext, aad: &wallet.to_ne_bytes() })
+            .map(Zeroizing::new)
+            .map_err(|_| KeystoreError::PasswordInvalid { wallet })?;
+
+        let key = WalletKey::from_secret(self.scheme, &secret).ok_or_else(invalid)?;
+        if key.public_key()? != self.pubkey {
+            Err(invalid())?;
+        }
+
+        Ok(key)
+    }
+
+    fn wallet(&self) -> Result<ByteHash> {
+        let wallet: ByteHash = (&self.wallet).try_into()?;
+        if (WalletData { pubkey: self.pubkey.clone(), scheme: self.scheme }).hash_with(self.hasher) != wallet {
+            Err(KeystoreError::KeyFileInvalid { wallet: self.wallet.clone() })?;
+        }
+
+        Ok(wallet)
+    }
+}
+
+/// Directory of password encrypted wallet keys, one file per wallet, with keys unlocked in memory for a limited time.
+///
+/// Wallets are named by their hash under the hash function of the chain the keystore is opened for.
+pub struct Keystore {
+    dir: PathBuf,
+    hasher: HashAlgorithm,
+    /// Unlocked keys with the instant they lock again, none if the timeout is too long to represent.
+    unlocked: HashMap<ByteHash, (Option<Instant>, WalletKey)>,
+}
+
+impl Keystore {
+    pub fn open(dir: impl AsRef<Path>, hasher: HashAlgorithm) -> Result<Keystore> {
+        fs::create_dir_all(dir.as_ref()).map_err(KeystoreError::from)?;
+
+        Ok(Keystore { dir: dir.as_ref().to_path_buf(), hasher, unlocked: HashMap::new() })
+    }
+
+    /// Wallets that have a key in the store.
+    pub fn list(&self) -> Result<Vec<ByteHash>> {
+        let mut wallets = vec![];
+        for entry in fs::read_dir(&self.dir).map_err(KeystoreError::from)? {
+            let path = entry.map_err(KeystoreError::from)?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some(KEY_FILE_EXTENSION) {
+                continue;
+            }
+
+            if let Some(wallet) = path.file_stem().and_then(|s| ByteHash::try_from(&s.to_string_lossy().into_owned()).ok()) {
+                wallets.push(wallet);
+            }
+        }
+
+        wallets.sort();
+        Ok(wallets)
+    }
+
+    /// Encrypts the key with the password and stores it, returning the hash of its wallet.
+    pub fn import(&self, key: &WalletKey, password: &str) -> Result<ByteHash> {
+        let key_file = KeyFile::seal(key, password, self.hasher)?;
+        let wallet = key_file.wallet()?;
+
+        self.write(wallet, &key_file)?;
+        Ok(wallet)
+    }
+
+    /// Stores a key file exported from another keystore of a chain with the same hash function, still encrypted
+    /// with its original password.
+    pub fn import_file(&self, json: &str) -> Result<ByteHash> {
+        let key_file: KeyFile = serde_json::from_str(json).map_err(|_| KeystoreError::KeyFileMalformed)?;
+        let wallet = key_file.wallet()?;
+        if key_file.hasher != self.hasher {
+            Err(KeystoreError::KeyFileInvalid { wallet: key_file.wallet.clone() })?;
+        }
+
+        self.write(wallet, &key_file)?;
+        Ok(wallet)
+    }
+
+    /// Encrypted key file of the wallet, for backup or moving it to another keystore.
+    pub fn export(&self, wallet: ByteHash) -> Result<String> {
+        let key_file = self.read(wallet)?;
+        Ok(serde_json::to_string_pretty(&key_file).expect("key file serialization"))
+    }
+
+    pub fn remove(&mut self, wallet: ByteHash) -> Result<()> {
+        self.lock(wallet);
+        fs::remove_file(self.path(wallet)).map_err(|_| KeystoreError::KeyNotFound { wallet })?;
+
+        Ok(())
+    }
+
+    /// Decrypts the wallet key and keeps it available for the given time.
+    pub fn unlock(&mut self, wallet: ByteHash, password: &str, timeout: Duration) -> Result<()> {
+        let key = self.read(wallet)?.open(password)?;
+        self.unlocked.insert(wallet, (Instant::now().checked_add(timeout), key));
+
+        Ok(())
+    }
+
+    pub fn lock(&mut self, wallet: ByteHash) {
+        self.unlocked.remove(&wallet);
+    }
+
+    /// Drops every unlocked key whose timeout has passed.
+    pub fn lock_expired(&mut self) {
+        let now = Instant::now();
+        self.unlocked.retain(|_, (expires, _)| expires.is_none_or(|e| e > now));
+    }
+
+    /// Unlocked key of the wallet, failing once its timeout has passed.
+    pub fn key(&mut self, wallet: ByteHash) -> Result<&WalletKey> {
+        self.lock_expired();
+
+        Ok(self.unlocked.get(&wallet).map(|(_, key)| key).ok_or(KeystoreError::KeyLocked { wallet })?)
+    }
+
+    fn path(&self, wallet: ByteHash) -> PathBuf {
+        self.dir.join(format!("{}.{}", String::from(wallet), KEY_FILE_EXTENSION))
+    }
+
+    fn read(&self, wallet: ByteHash) -> Result<KeyFile> {
+        let json = fs::read_to_string(self.path(wallet)).map_err(|_| KeystoreError::KeyNotFound { wallet })?;
+        let key_file: KeyFile = serde_json::from_str(&json).map_err(|_| KeystoreError::KeyFileMalformed)?;
+
+        if key_file.wallet()? != wallet {
+            Err(KeystoreError::KeyFileInvalid { wallet: key_file.wallet.clone() })?;
+        }
+
+        Ok(key_file)
+    }
+
+    fn write(&self, wallet: ByteHash, key_file: &KeyFile) -> Result<()> {
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+        let mut file = options.open(self.path(wallet)).map_err(|e| match e.kind() {
+            std::io::ErrorKind::AlreadyExists => KeystoreError::KeyDuplicate { wallet },
+            _ => KeystoreError::Io(e),
+        })?;
+
+        file.write_all(&serde_json::to_vec_pretty(key_file).expect("key file serialization"))
+            .map_err(KeystoreError::from)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keystore(name: &str, hasher: HashAlgorithm) -> Keystore {
+        let dir = std::env::temp_dir().join(format!("rschain_keystore_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        Keystore::open(dir, hasher).unwrap()
+    }
+
+    #[test]
+    fn wallets_named_under_chain_hash_function() {
+        let key = WalletKey::generate(Scheme::Ed25519).unwrap();
+        let data = key.wallet_data().unwrap();
+        let (mut blake3, sha256) = (keystore("blake3", HashAlgorithm::Blake3), keystore("sha256", HashAlgorithm::Sha256));
+
+        let wallet = blake3.import(&key, "password").unwrap();
+        assert_eq!(wallet, data.hash_with(HashAlgorithm::Blake3));
+        assert_eq!(blake3.list().unwrap(), vec![wallet]);
+
+        blake3.unlock(wallet, "password", Duration::from_secs(60)).unwrap();
+        assert_eq!(blake3.key(wallet).unwrap().public_key().unwrap(), data.pubkey);
+
+        // A key file names its wallet for one hash function only.
+        let exported = blake3.export(wallet).unwrap();
+        assert_eq!(sha256.import_file(&exported).unwrap_err().code(), 6005);
+        assert_eq!(sha256.import(&key, "password").unwrap(), data.hash_with(HashAlgorithm::Sha256));
+
+        let _ = fs::remove_dir_all(&blake3.dir);
+        let _ = fs::remove_dir_all(&sha256.dir);
+    }
+
+    #[test]
+    fn unlock_needs_password_and_expires() {
+        let key = WalletKey::generate(Scheme::Ed25519).unwrap();
+        let mut keystore = keystore("unlock", HashAlgorithm::default());
+        let wallet = keystore.import(&key, "password").unwrap();
+
+        assert_eq!(keystore.unlock(wallet, "passw0rd", Duration::from_secs(60)).unwrap_err().code(), 6003);
+        assert_eq!(keystore.key(wallet).err().map(|e| e.code()), Some(6004));
+
+        keystore.unlock(wallet, "password", Duration::from_millis(100)).unwrap();
+        assert!(keystore.key(wallet).is_ok());
+
+        std::thread::sleep(Duration::from_millis(150));
+        assert_eq!(keystore.key(wallet).err().map(|e| e.code()), Some(6004));
+
+        let _ = fs::remove_dir_all(&keystore.dir);
+    }
+}
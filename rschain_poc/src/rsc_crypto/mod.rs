@@ -1 +1,5 @@
 pub mod signature;
+pub mod payload;
+pub mod verify;
+pub mod keystore;
+pub mod hd;
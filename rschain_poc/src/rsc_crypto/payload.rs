@@ -0,0 +1,37 @@
+use crate::rsc_blockdata::BlockData;
+
+/// Tag opening every signing payload, so a signature over block data is never valid in another context.
+pub const SIGNING_DOMAIN: &[u8] = b"RSCHAIN_TX";
+
+/// Layout version of the signing payload, bumped whenever the signed bytes change.
+pub const SIGNING_VERSION: u8 = 2;
+
+/// Builds the bytes a block signature covers.
+///
+/// The block data is prefixed with the domain tag, the length prefixed chain ID, the payload version and
+/// the signer's sequence number, binding the signature to one chain, one payload layout and one use.
+#[derive(Debug, Clone, Copy)]
+pub struct SigningPayload<'a> {
+    chain_id: &'a str,
+    version: u8,
+}
+
+impl<'a> SigningPayload<'a> {
+    pub fn new(chain_id: &'a str) -> SigningPayload<'a> {
+        SigningPayload { chain_id, version: SIGNING_VERSION }
+    }
+
+    pub fn build(&self, sequence: u64, data: &BlockData) -> Vec<u8> {
+        let data_vec: Vec<u8> = data.into();
+
+        let mut payload = Vec::with_capacity(SIGNING_DOMAIN.len() + self.chain_id.len() + data_vec.len() + 13);
+        payload.extend_from_slice(SIGNING_DOMAIN);
+        payload.extend_from_slice(&(self.chain_id.len() as u32).to_be_bytes());
+        payload.extend_from_slice(self.chain_id.as_bytes());
+        payload.push(self.version);
+        payload.extend_from_slice(&sequence.to_be_bytes());
+        payload.extend_from_slice(&data_vec);
+
+        payload
+    }
+}
@@ -1,13 +1,284 @@
+use ed25519_dalek::{Signer as _, SigningKey, VerifyingKey};
+use curve25519_dalek::edwards::CompressedEdwardsY;
+use k256::ecdsa::{self, RecoveryId};
 use openssl::{pkey::{PKey, Public, Private}, sign::{Verifier, Signer}, hash::MessageDigest};
+use serde::{Serialize, Deserialize};
 
-pub fn sign(key: &PKey<Private>, data: &[u8]) -> anyhow::Result<Vec<u8>> {
+use crate::rsc_error::{Result, ValidationError};
+use super::verify::SignatureJob;
+
+/// Signature algorithm a wallet's public key belongs to.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Scheme {
+    #[default]
+    Ed25519,
+    Rsa,
+    Secp256k1,
+}
+
+impl Scheme {
+    /// Scheme of wallets serialized before the scheme tag existed.
+    pub fn legacy() -> Scheme {
+        Scheme::Rsa
+    }
+
+    pub fn check_public_key(&self, pubkey: &[u8]) -> Result<()> {
+        match self {
+            Scheme::Ed25519 => Ed25519::check_public_key(pubkey),
+            Scheme::Rsa => RsaPkcs1::check_public_key(pubkey),
+            Scheme::Secp256k1 => Secp256k1::check_public_key(pubkey),
+        }
+    }
+
+    /// Checks a hex encoded signature against a public key of this scheme.
+    pub fn check(&self, pubkey: &[u8], data: &[u8], signature: &str) -> Result<bool> {
+        let signature_bytes = hex::decode(signature)?;
+        self.verify(pubkey, data, &signature_bytes)
+    }
+
+    pub fn verify(&self, pubkey: &[u8], data: &[u8], signature: &[u8]) -> Result<bool> {
+        match self {
+            Scheme::Ed25519 => Ed25519::verify(pubkey, data, signature),
+            Scheme::Rsa => RsaPkcs1::verify(pubkey, data, signature),
+            Scheme::Secp256k1 => Secp256k1::verify(pubkey, data, signature),
+        }
+    }
+}
+
+/// Key generation, signing and verification of one signature algorithm.
+///
+/// Public keys are handled in the text encoding wallets carry them in.
+pub trait SignatureScheme {
+    const SCHEME: Scheme;
+    type PrivateKey;
+
+    fn generate() -> Result<Self::PrivateKey>;
+    fn public_key(key: &Self::PrivateKey) -> Result<String>;
+    fn sign(key: &Self::PrivateKey, data: &[u8]) -> Result<Vec<u8>>;
+
+    fn check_public_key(pubkey: &[u8]) -> Result<()>;
+    fn verify(pubkey: &[u8], data: &[u8], signature: &[u8]) -> Result<bool>;
+}
+
+/// Ed25519 with hex encoded public keys.
+pub struct Ed25519;
+
+impl Ed25519 {
+    fn parse_public_key(pubkey: &[u8]) -> Result<VerifyingKey> {
+        let bytes: [u8; 32] = hex::decode(pubkey)?
+            .try_into()
+            .map_err(|_| ValidationError::InvalidPublicKey)?;
+
+        Ok(VerifyingKey::from_bytes(&bytes).map_err(|_| ValidationError::InvalidPublicKey)?)
+    }
+
+    /// Whether the job can join a batch without accepting anything `verify_strict` would reject.
+    pub(crate) fn batchable(job: &SignatureJob) -> bool {
+        let Ok(public_key) = Self::parse_public_key(&job.pubkey) else {
+            return false;
+        };
+
+        let Ok(signature) = ed25519_dalek::Signature::from_slice(&job.signature) else {
+            return false;
+        };
+
+        !public_key.is_weak() && CompressedEdwardsY(*signature.r_bytes()).decompress().is_some_and(|r| !r.is_small_order())
+    }
+
+    /// Verifies all jobs at once, true only if every signature is valid.
+    pub(crate) fn verify_batch<'a>(jobs: impl Iterator<Item = &'a SignatureJob>) -> bool {
+        let mut messages = Vec::new();
+        let mut signatures = Vec::new();
+        let mut public_keys = Vec::new();
+        for job in jobs {
+            let (Ok(public_key), Ok(signature)) = (Self::parse_public_key(&job.pubkey), ed25519_dalek::Signature::from_slice(&job.signature)) else {
+                return false;
+            };
+
+            messages.push(job.payload.as_slice());
+            signatures.push(signature);
+            public_keys.push(public_key);
+        }
+
+        ed25519_dalek::verify_batch(&messages, &signatures, &public_keys).is_ok()
+    }
+}
+
+impl SignatureScheme for Ed25519 {
+    const SCHEME: Scheme = Scheme::Ed25519;
+    type PrivateKey = SigningKey;
+
+    fn generate() -> Result<SigningKey> {
+        Ok(SigningKey::from_bytes(&rand::random::<[u8; 32]>()))
+    }
+
+    fn public_key(key: &SigningKey) -> Result<String> {
+        Ok(hex::encode(key.verifying_key().to_bytes()))
+    }
+
+    fn sign(key: &SigningKey, data: &[u8]) -> Result<Vec<u8>> {
+        Ok(key.sign(data).to_bytes().to_vec())
+    }
+
+    fn check_public_key(pubkey: &[u8]) -> Result<()> {
+        Self::parse_public_key(pubkey).map(|_| ())
+    }
+
+    fn verify(pubkey: &[u8], data: &[u8], signature: &[u8]) -> Result<bool> {
+        let public_key = Self::parse_public_key(pubkey)?;
+        let Ok(signature) = ed25519_dalek::Signature::from_slice(signature) else {
+            return Ok(false);
+        };
+
+        Ok(public_key.verify_strict(data, &signature).is_ok())
+    }
+}
+
+/// RSA PKCS#1 with SHA-256 and PEM encoded public keys, kept for wallets created before Ed25519.
+pub struct RsaPkcs1;
+
+impl SignatureScheme for RsaPkcs1 {
+    const SCHEME: Scheme = Scheme::Rsa;
+    type PrivateKey = PKey<Private>;
+
+    fn generate() -> Result<PKey<Private>> {
+        Ok(PKey::from_rsa(openssl::rsa::Rsa::generate(2048)?)?)
+    }
+
+    fn public_key(key: &PKey<Private>) -> Result<String> {
+        Ok(String::from_utf8(key.public_key_to_pem()?).map_err(|_| ValidationError::InvalidPublicKey)?)
+    }
+
+    fn sign(key: &PKey<Private>, data: &[u8]) -> Result<Vec<u8>> {
+        sign(key, data)
+    }
+
+    fn check_public_key(pubkey: &[u8]) -> Result<()> {
+        PKey::public_key_from_pem(pubkey).map_err(|_| ValidationError::InvalidPublicKey)?;
+        Ok(())
+    }
+
+    fn verify(pubkey: &[u8], data: &[u8], signature: &[u8]) -> Result<bool> {
+        let public_key = PKey::public_key_from_pem(pubkey).map_err(|_| ValidationError::InvalidPublicKey)?;
+        check(&public_key, data, signature)
+    }
+}
+
+/// ECDSA over secp256k1 with SHA-256 and hex encoded compressed public keys.
+///
+/// Signatures are 65 bytes, the low-S `r || s` followed by the recovery id, so the public key can be
+/// recovered from the signature alone. High-S signatures are rejected to prevent malleability.
+pub struct Secp256k1;
+
+impl Secp256k1 {
+    fn parse_public_key(pubkey: &[u8]) -> Result<ecdsa::VerifyingKey> {
+        Ok(ecdsa::VerifyingKey::from_sec1_bytes(&hex::decode(pubkey)?).map_err(|_| ValidationError::InvalidPublicKey)?)
+    }
+
+    fn parse_signature(signature: &[u8]) -> Option<(ecdsa::Signature, RecoveryId)> {
+        let (rs, recovery) = signature.split_last_chunk::<1>()?;
+        let signature = ecdsa::Signature::from_slice(rs).ok()?;
+        if signature.normalize_s().is_some() {
+            return None;
+        }
+
+        Some((signature, RecoveryId::from_byte(recovery[0])?))
+    }
+
+    fn encode_public_key(public_key: &ecdsa::VerifyingKey) -> String {
+        hex::encode(public_key.to_encoded_point(true).as_bytes())
+    }
+
+    /// Canonical wallet encoding of a public key given in any accepted form: lower-case hex of the compressed point.
+    pub fn normalize_public_key(pubkey: &[u8]) -> Result<String> {
+        Ok(Self::encode_public_key(&Self::parse_public_key(pubkey)?))
+    }
+
+    /// Public key, in canonical wallet encoding, of whoever produced the signature over the data.
+    pub fn recover(data: &[u8], signature: &[u8]) -> Result<String> {
+        let (signature, recovery) = Self::parse_signature(signature).ok_or(ValidationError::InvalidSignature)?;
+        let public_key = ecdsa::VerifyingKey::recover_from_msg(data, &signature, recovery)
+            .map_err(|_| ValidationError::InvalidSignature)?;
+
+        Ok(Self::encode_public_key(&public_key))
+    }
+}
+
+impl SignatureScheme for Secp256k1 {
+    const SCHEME: Scheme = Scheme::Secp256k1;
+    type PrivateKey = ecdsa::SigningKey;
+
+    fn generate() -> Result<ecdsa::SigningKey> {
+        loop {
+            if let Ok(key) = ecdsa::SigningKey::from_slice(&rand::random::<[u8; 32]>()) {
+                return Ok(key);
+            }
+        }
+    }
+
+    fn public_key(key: &ecdsa::SigningKey) -> Result<String> {
+        Ok(Self::encode_public_key(key.verifying_key()))
+    }
+
+    fn sign(key: &ecdsa::SigningKey, data: &[u8]) -> Result<Vec<u8>> {
+        let (signature, recovery) = key.sign_recoverable(data).map_err(|_| ValidationError::InvalidSignature)?;
+
+        let mut bytes = signature.to_vec();
+        bytes.push(recovery.to_byte());
+        Ok(bytes)
+    }
+
+    fn check_public_key(pubkey: &[u8]) -> Result<()> {
+        Self::parse_public_key(pubkey).map(|_| ())
+    }
+
+    fn verify(pubkey: &[u8], data: &[u8], signature: &[u8]) -> Result<bool> {
+        let public_key = Self::parse_public_key(pubkey)?;
+        let Some((signature, _)) = Self::parse_signature(signature) else {
+            return Ok(false);
+        };
+
+        Ok(k256::ecdsa::signature::Verifier::verify(&public_key, data, &signature).is_ok())
+    }
+}
+
+pub fn sign(key: &PKey<Private>, data: &[u8]) -> Result<Vec<u8>> {
     let mut signer = Signer::new(MessageDigest::sha256(), key)?;
     signer.update(data)?;
-    return Ok(signer.sign_to_vec()?);
+    Ok(signer.sign_to_vec()?)
 }
 
-pub fn check(key: &PKey<Public>, data: &[u8], signature: &[u8]) -> anyhow::Result<bool> {
+pub fn check(key: &PKey<Public>, data: &[u8], signature: &[u8]) -> Result<bool> {
     let mut verifyer = Verifier::new(MessageDigest::sha256(), key)?;
     verifyer.update(data)?;
-    return Ok(verifyer.verify(signature)?);
+    Ok(verifyer.verify(signature)?)
+}
+
+/// Checks a hex encoded signature against a PEM encoded public key.
+pub fn check_pem(pubkey: &[u8], data: &[u8], signature: &str) -> Result<bool> {
+    Scheme::Rsa.check(pubkey, data, signature)
+}
+
+const MULTISIG_SEPARATOR: char = ',';
+const SIGNER_SEPARATOR: char = ':';
+
+/// Packs several signatures into a single block signature field, each along with the index of the wallet key that made it.
+pub fn encode_multi(signatures: &[(usize, Vec<u8>)]) -> String {
+    signatures
+        .iter()
+        .map(|(signer, signature)| format!("{}{}{}", signer, SIGNER_SEPARATOR, hex::encode(signature)))
+        .collect::<Vec<String>>()
+        .join(&MULTISIG_SEPARATOR.to_string())
+}
+
+pub fn decode_multi(signature: &str) -> Result<Vec<(usize, Vec<u8>)>> {
+    signature
+        .split(MULTISIG_SEPARATOR)
+        .map(|part| {
+            let (signer, signature) = part.split_once(SIGNER_SEPARATOR).ok_or(ValidationError::InvalidSignature)?;
+            let signer = signer.parse().map_err(|_| ValidationError::InvalidSignature)?;
+
+            Ok((signer, hex::decode(signature)?))
+        })
+        .collect()
 }
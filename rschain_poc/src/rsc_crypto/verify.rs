@@ -0,0 +1,146 @@
+use std::{collections::HashSet, sync::Mutex, thread};
+
+use sha2::{Digest, Sha256};
+
+use crate::{rsc_util::hash::ByteHash, rsc_error::Result};
+use super::signature::{Ed25519, Scheme};
+
+/// Verified signatures kept before the cache is cleared, bounding its memory.
+pub const SIGNATURE_CACHE_CAPACITY: usize = 1 << 16;
+
+/// Fewer jobs than this are verified on the calling thread, as spawning threads would cost more than it saves.
+const MIN_PARALLEL_JOBS: usize = 16;
+
+/// A signature to verify, carrying everything needed to check it without ledger state.
+#[derive(Debug, Clone)]
+pub struct SignatureJob {
+    pub scheme: Scheme,
+    pub pubkey: Vec<u8>,
+    pub payload: Vec<u8>,
+    pub signature: Vec<u8>,
+}
+
+impl SignatureJob {
+    fn key(&self) -> ByteHash {
+        let mut hasher = Sha256::new();
+        hasher.update(serde_json::to_vec(&self.scheme).expect("scheme serialization"));
+        for field in [&self.pubkey, &self.payload, &self.signature] {
+            hasher.update((field.len() as u64).to_be_bytes());
+            hasher.update(field);
+        }
+
+        hasher.finalize().try_into().expect("hasher/Hash incompat")
+    }
+
+    fn verify(&self) -> bool {
+        self.scheme.verify(&self.pubkey, &self.payload, &self.signature).unwrap_or(false)
+    }
+}
+
+/// Signatures already found valid, so re-applying a block on another fork or after an undo skips verification.
+#[derive(Debug, Default)]
+pub struct SignatureCache {
+    verified: Mutex<HashSet<ByteHash>>,
+}
+
+impl SignatureCache {
+    pub fn new() -> SignatureCache {
+        SignatureCache::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.verified.lock().expect("signature cache poisoned").len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Checks a hex encoded signature, consulting the cache first and remembering it if valid.
+    pub fn check(&self, scheme: Scheme, pubkey: &[u8], payload: &[u8], signature: &str) -> Result<bool> {
+        let signature_bytes = hex::decode(signature)?;
+        self.verify(scheme, pubkey, payload, &signature_bytes)
+    }
+
+    pub fn verify(&self, scheme: Scheme, pubkey: &[u8], payload: &[u8], signature: &[u8]) -> Result<bool> {
+        let job = SignatureJob { scheme, pubkey: pubkey.to_vec(), payload: payload.to_vec(), signature: signature.to_vec() };
+        if self.contains(&job) {
+            return Ok(true);
+        }
+
+        let valid = scheme.verify(pubkey, payload, signature)?;
+        if valid {
+            self.insert([job.key()]);
+        }
+
+        Ok(valid)
+    }
+
+    /// Verifies the jobs in parallel, batching Ed25519 ones, and caches the valid ones.
+    ///
+    /// Results are in job order.
+    pub fn verify_batch(&self, jobs: &[SignatureJob]) -> Vec<bool> {
+        let keys: Vec<ByteHash> = jobs.iter().map(SignatureJob::key).collect();
+        let mut results = {
+            let verified = self.verified.lock().expect("signature cache poisoned");
+            keys.iter().map(|k| verified.contains(k)).collect::<Vec<bool>>()
+        };
+
+        let pending: Vec<usize> = (0..jobs.len()).filter(|&i| !results[i]).collect();
+        let (batched, single): (Vec<usize>, Vec<usize>) = pending
+            .into_iter()
+            .partition(|&i| jobs[i].scheme == Scheme::Ed25519 && Ed25519::batchable(&jobs[i]));
+
+        if batched.len() > 1 && Ed25519::verify_batch(batched.iter().map(|&i| &jobs[i])) {
+            batched.iter().for_each(|&i| results[i] = true);
+        } else {
+            Self::verify_parallel(jobs, &batched, &mut results);
+        }
+        Self::verify_parallel(jobs, &single, &mut results);
+
+        self.insert((0..jobs.len()).filter(|&i| results[i]).map(|i| keys[i]));
+        results
+    }
+
+    fn verify_parallel(jobs: &[SignatureJob], indices: &[usize], results: &mut [bool]) {
+        if indices.is_empty() {
+            return;
+        }
+
+        let threads = thread::available_parallelism().map_or(1, |n| n.get()).min(indices.len());
+        if threads == 1 || indices.len() < MIN_PARALLEL_JOBS {
+            indices.iter().for_each(|&i| results[i] = jobs[i].verify());
+            return;
+        }
+
+        let chunk_size = indices.len().div_ceil(threads);
+
+        let verified: Vec<(usize, bool)> = thread::scope(|scope| {
+            let handles: Vec<_> = indices
+                .chunks(chunk_size)
+                .map(|chunk| scope.spawn(move || chunk.iter().map(|&i| (i, jobs[i].verify())).collect::<Vec<_>>()))
+                .collect();
+
+            handles.into_iter().flat_map(|h| h.join().expect("verification thread panicked")).collect()
+        });
+
+        for (i, valid) in verified {
+            results[i] = valid;
+        }
+    }
+
+    fn contains(&self, job: &SignatureJob) -> bool {
+        self.verified.lock().expect("signature cache poisoned").contains(&job.key())
+    }
+
+    fn insert(&self, keys: impl IntoIterator<Item = ByteHash>) {
+        let mut verified = self.verified.lock().expect("signature cache poisoned");
+        for key in keys {
+            if verified.len() >= SIGNATURE_CACHE_CAPACITY {
+                verified.clear();
+            }
+
+            verified.insert(key);
+        }
+    }
+}
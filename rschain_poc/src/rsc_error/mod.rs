@@ -0,0 +1,152 @@
+use crate::rsc_bank::BankError;
+use crate::rsc_blockdata::block_data::LedgerModel;
+use crate::rsc_core::{chain::StoreError, shard::ShardError};
+use crate::rsc_crypto::{keystore::KeystoreError, signature::Scheme};
+use crate::rsc_miner::MiningError;
+use crate::rsc_util::{hash::HashError, address::Network};
+
+/// Numeric code identifying the kind of an error, kept stable so it can be relied on over RPC.
+///
+/// Codes are grouped by error type: 1xxx validation, 2xxx bank, 3xxx shard, 4xxx store, 5xxx mining, 6xxx keystore.
+pub trait ErrorCode {
+    fn code(&self) -> u32;
+}
+
+/// Block data that is malformed regardless of the ledger state it would be applied to.
+#[derive(thiserror::Error, Debug)]
+pub enum ValidationError {
+    #[error("InvalidHash: {0}")]
+    InvalidHash(#[from] HashError),
+
+    #[error("InvalidHex: {0}")]
+    InvalidHex(#[from] hex::FromHexError),
+
+    #[error("InvalidAmount: {amount}")]
+    InvalidAmount { amount: f64 },
+
+    #[error("InvalidPublicKey")]
+    InvalidPublicKey,
+
+    #[error("InvalidSignature")]
+    InvalidSignature,
+
+    #[error("MultisigInvalid: threshold {threshold} of {signers} signers")]
+    MultisigInvalid { signers: usize, threshold: usize },
+
+    #[error("BatchEmpty")]
+    BatchEmpty,
+
+    #[error("NoInputs")]
+    NoInputs,
+
+    #[error("GenesisMisplaced: at height {height}")]
+    GenesisMisplaced { height: u64 },
+
+    #[error("LedgerModelMismatch: expected {expected:?}, found {found:?}")]
+    LedgerModelMismatch { expected: LedgerModel, found: LedgerModel },
+
+    #[error("UnsupportedBlockData")]
+    UnsupportedBlockData,
+
+    #[error("MnemonicInvalid")]
+    MnemonicInvalid,
+
+    #[error("DerivationPathInvalid: {path}")]
+    DerivationPathInvalid { path: String },
+
+    #[error("DerivationUnsupported: {scheme:?}")]
+    DerivationUnsupported { scheme: Scheme },
+
+    #[error("AddressNetworkMismatch: expected {expected:?}, found {found:?}")]
+    AddressNetworkMismatch { expected: Network, found: Network },
+
+    #[error("Crypto: {0}")]
+    Crypto(#[from] openssl::error::ErrorStack),
+}
+
+impl ErrorCode for ValidationError {
+    fn code(&self) -> u32 {
+        match self {
+            ValidationError::InvalidHash(_) => 1001,
+            ValidationError::InvalidHex(_) => 1002,
+            ValidationError::InvalidAmount { .. } => 1003,
+            ValidationError::InvalidPublicKey => 1004,
+            ValidationError::MultisigInvalid { .. } => 1005,
+            ValidationError::BatchEmpty => 1006,
+            ValidationError::NoInputs => 1007,
+            ValidationError::GenesisMisplaced { .. } => 1008,
+            ValidationError::LedgerModelMismatch { .. } => 1009,
+            ValidationError::UnsupportedBlockData => 1010,
+            ValidationError::Crypto(_) => 1011,
+            ValidationError::InvalidSignature => 1012,
+            ValidationError::MnemonicInvalid => 1013,
+            ValidationError::DerivationPathInvalid { .. } => 1014,
+            ValidationError::DerivationUnsupported { .. } => 1015,
+            ValidationError::AddressNetworkMismatch { .. } => 1016,
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error(transparent)]
+    Validation(#[from] ValidationError),
+
+    #[error(transparent)]
+    Bank(#[from] BankError),
+
+    #[error(transparent)]
+    Shard(#[from] ShardError),
+
+    #[error(transparent)]
+    Store(#[from] StoreError),
+
+    #[error(transparent)]
+    Mining(#[from] MiningError),
+
+    #[error(transparent)]
+    Keystore(#[from] KeystoreError),
+}
+
+impl Error {
+    /// Innermost error, looking through the context blocks were rejected with.
+    pub fn root(&self) -> &Error {
+        match self {
+            Error::Store(StoreError::Rejected { source, .. }) => source.root(),
+            _ => self,
+        }
+    }
+}
+
+impl ErrorCode for Error {
+    fn code(&self) -> u32 {
+        match self {
+            Error::Validation(e) => e.code(),
+            Error::Bank(e) => e.code(),
+            Error::Shard(e) => e.code(),
+            Error::Store(e) => e.code(),
+            Error::Mining(e) => e.code(),
+            Error::Keystore(e) => e.code(),
+        }
+    }
+}
+
+impl From<HashError> for Error {
+    fn from(e: HashError) -> Self {
+        ValidationError::from(e).into()
+    }
+}
+
+impl From<hex::FromHexError> for Error {
+    fn from(e: hex::FromHexError) -> Self {
+        ValidationError::from(e).into()
+    }
+}
+
+impl From<openssl::error::ErrorStack> for Error {
+    fn from(e: openssl::error::ErrorStack) -> Self {
+        ValidationError::from(e).into()
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
@@ -1,28 +1,451 @@
-use std::time::Instant;
+use std::{sync::{Arc, atomic::{AtomicBool, AtomicU64, Ordering}}, thread::{self, JoinHandle, Thread}, time::{Duration, Instant}};
 
-use crate::{Block, rsc_core::shard::Shard};
+use crate::{rsc_core::{block::{Block, Nonce}, shard::{Shard, Tip, TipWatch}}, rsc_bank::ledger::Ledger, rsc_error::{ErrorCode, Result}};
+use crate::rsc_util::hash::ByteHash;
+
+/// How often a running job checks the shard tip.
+const TIP_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Attempts a worker claims from the shared budget at once, checking the deadline only between claims.
+const ATTEMPT_CHUNK: u64 = 4096;
 
 #[derive(thiserror::Error, Debug)]
 pub enum MiningError {
     #[error("max time exceeded")]
     MaxTimeExceeded,
+
+    #[error("max attempts exceeded")]
+    MaxAttemptsExceeded,
+
+    #[error("cancelled")]
+    Cancelled,
+
+    #[error("nonce space exhausted")]
+    NonceSpaceExhausted,
+}
+
+impl ErrorCode for MiningError {
+    fn code(&self) -> u32 {
+        match self {
+            MiningError::MaxTimeExceeded => 5001,
+            MiningError::MaxAttemptsExceeded => 5002,
+            MiningError::Cancelled => 5003,
+            MiningError::NonceSpaceExhausted => 5004,
+        }
+    }
+}
+
+/// Block meeting the shard difficulty, with the worker thread that found it.
+#[derive(Debug, Clone)]
+pub struct Solution {
+    pub block: Block,
+    pub thread: usize,
+}
+
+/// Limits after which mining gives up, none meaning unlimited.
+///
+/// Attempts are counted across all workers and, for a job, across restarts. Workers claim them in chunks,
+/// so the time limit may be overrun by the time a chunk takes.
+#[derive(Debug, Clone, Copy)]
+pub struct Budget {
+    pub time: Option<Duration>,
+    pub attempts: Option<u64>,
+}
+
+impl Default for Budget {
+    fn default() -> Self {
+        Self {
+            time: Some(Duration::from_secs(5)),
+            attempts: None,
+        }
+    }
+}
+
+/// Proof of work miner splitting the nonce space across worker threads.
+///
+/// Worker `i` of `n` tries nonces `i, i + n, i + 2n, ...`, so a single worker tries them in order
+/// and always finds the same solution. Once the nonces run out the block moves on to the next extra
+/// nonce and a current timestamp, and the nonces are tried again.
+#[derive(Debug, Clone)]
+pub struct Miner {
+    threads: usize,
+    budget: Budget,
+    max_nonce: Nonce,
 }
 
-pub fn mine_block(shard: &Shard, mut block: Block) -> anyhow::Result<Block> {
-    let start_time = Instant::now();
+impl Default for Miner {
+    fn default() -> Self {
+        Self::new(1)
+    }
+}
+
+impl Miner {
+    pub fn new(threads: usize) -> Self {
+        Self {
+            threads: threads.max(1),
+            budget: Budget::default(),
+            max_nonce: Nonce::MAX,
+        }
+    }
+
+    /// Miner with one worker per available CPU.
+    pub fn parallel() -> Self {
+        Self::new(thread::available_parallelism().map_or(1, |n| n.get()))
+    }
+
+    pub fn with_budget(mut self, budget: Budget) -> Self {
+        self.budget = budget;
+        self
+    }
+
+    /// Largest nonce tried before moving on to the next extra nonce.
+    pub fn with_max_nonce(mut self, max_nonce: Nonce) -> Self {
+        self.max_nonce = max_nonce;
+        self
+    }
+
+    pub fn threads(&self) -> usize {
+        self.threads
+    }
 
-    for attempt in 0.. {
-        block.update_nonce(attempt);
+    /// Finds a nonce meeting the shard difficulty, stopping all workers on the first solution.
+    pub fn mine<L: Ledger>(&self, shard: &Shard<L>, block: Block) -> Result<Solution> {
+        let control = Control::new(self.budget);
 
-        if shard.check_difficulty(&block) {
-            return Ok(block);
+        match self.round(block, shard.tip(), &control, None) {
+            Round::Found(solution) => Ok(*solution),
+            Round::Stale(_) | Round::Stopped => Err(control.reason().into()),
         }
+    }
+
+    /// Mines in the background on blocks built by `template` on top of the shard tip.
+    ///
+    /// Whenever the tip changes the work in progress is dropped and the job restarts on a fresh
+    /// template. The template gets the tip hash, none while the shard is empty.
+    pub fn start<L, F>(&self, shard: &Shard<L>, mut template: F) -> MiningJob
+    where
+        L: Ledger,
+        F: FnMut(Option<ByteHash>) -> Block + Send + 'static,
+    {
+        let miner = self.clone();
+        let watch = shard.watch();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let control = Control { cancelled: cancelled.clone(), ..Control::new(self.budget) };
+
+        let handle = thread::spawn(move || {
+            let mut tip = watch.get();
+            loop {
+                match miner.round(template(tip.hash), tip, &control, Some(&watch)) {
+                    Round::Found(solution) => return Ok(*solution),
+                    Round::Stale(new_tip) => tip = new_tip,
+                    Round::Stopped => return Err(control.reason().into()),
+                }
+            }
+        });
+
+        MiningJob { cancelled, handle: Some(handle) }
+    }
+
+    /// Mines one template until a worker finds a solution, the budget runs out, the job is
+    /// cancelled or the watched tip moves on.
+    fn round(&self, block: Block, tip: Tip, control: &Control, watch: Option<&TipWatch>) -> Round {
+        let stop = AtomicBool::new(false);
+        let monitor = thread::current();
+
+        thread::scope(|scope| {
+            let workers: Vec<_> = (0..self.threads)
+                .map(|thread| {
+                    let block = block.clone();
+                    let (stop, monitor) = (&stop, monitor.clone());
+                    scope.spawn(move || self.work(thread, block, tip, control, stop, monitor))
+                })
+                .collect();
+
+            let mut stale = None;
+            while !workers.iter().all(|worker| worker.is_finished()) {
+                let new_tip = watch.map(|watch| watch.get()).filter(|new_tip| *new_tip != tip);
+                if new_tip.is_some() || control.cancelled.load(Ordering::Relaxed) {
+                    stale = stale.or(new_tip);
+                    stop.store(true, Ordering::Relaxed);
+                }
+
+                thread::park_timeout(TIP_POLL_INTERVAL);
+            }
+
+            let solution = workers.into_iter()
+                .filter_map(|worker| worker.join().expect("mining worker panicked"))
+                .next();
+
+            match (solution, stale) {
+                (Some(solution), _) => Round::Found(Box::new(solution)),
+                (None, Some(new_tip)) => Round::Stale(new_tip),
+                (None, None) => Round::Stopped,
+            }
+        })
+    }
+
+    fn work(&self, thread: usize, mut block: Block, tip: Tip, control: &Control, stop: &AtomicBool, monitor: Thread) -> Option<Solution> {
+        // Wake the monitor as soon as this worker is done.
+        let _wake = WakeOnDrop(monitor);
+        let algorithm = block.hash_algorithm(tip.hasher);
+        let mut claimed = 0;
 
-        if Instant::now().duration_since(start_time).as_secs() > 5 {
-            return Err(MiningError::MaxTimeExceeded.into());
+        loop {
+            for attempt in (thread as Nonce..=self.max_nonce).step_by(self.threads) {
+                if claimed == 0 {
+                    claimed = control.claim();
+                }
+
+                if stop.load(Ordering::Relaxed) || claimed == 0 {
+                    return None;
+                }
+
+                claimed -= 1;
+
+                block.update_nonce(attempt, algorithm);
+
+                if block.meets_difficulty(tip.difficulty) {
+                    // Only the first worker to claim the solution reports it.
+                    return stop.compare_exchange(false, true, Ordering::AcqRel, Ordering::Relaxed).ok()
+                        .map(|_| Solution { block, thread });
+                }
+            }
+
+            if stop.load(Ordering::Relaxed) || control.spent() {
+                return None;
+            }
+
+            let Some(extra_nonce) = block.extra_nonce.checked_add(1) else {
+                control.exhausted.store(true, Ordering::Relaxed);
+                return None;
+            };
+
+            block.refresh(extra_nonce);
         }
     }
+}
 
-    panic!("infinit. loop exit")
+pub fn mine_block<L: Ledger>(shard: &Shard<L>, block: Block) -> Result<Block> {
+    Miner::default().mine(shard, block).map(|solution| solution.block)
 }
 
+/// Handle to mining running in the background, cancelled when dropped.
+pub struct MiningJob {
+    cancelled: Arc<AtomicBool>,
+    handle: Option<JoinHandle<Result<Solution>>>,
+}
+
+impl MiningJob {
+    /// Stops the workers, making the job finish with `MiningError::Cancelled` unless it already found a solution.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.handle.as_ref().is_none_or(|handle| handle.is_finished())
+    }
+
+    /// Blocks until the job finds a solution or gives up.
+    pub fn wait(mut self) -> Result<Solution> {
+        let handle = self.handle.take().expect("handle is only taken by wait");
+        handle.join().expect("mining job panicked")
+    }
+}
+
+impl Drop for MiningJob {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+enum Round {
+    Found(Box<Solution>),
+    Stale(Tip),
+    Stopped,
+}
+
+/// Budget and cancellation shared by all workers of a mining run.
+struct Control {
+    cancelled: Arc<AtomicBool>,
+    deadline: Option<Instant>,
+    attempts: AtomicU64,
+    max_attempts: Option<u64>,
+    exhausted: AtomicBool,
+}
+
+impl Control {
+    fn new(budget: Budget) -> Self {
+        Self {
+            cancelled: Arc::new(AtomicBool::new(false)),
+            deadline: budget.time.map(|time| Instant::now() + time),
+            attempts: AtomicU64::new(0),
+            max_attempts: budget.attempts,
+            exhausted: AtomicBool::new(false),
+        }
+    }
+
+    /// Claims up to `ATTEMPT_CHUNK` attempts, none once the job is cancelled or the budget is spent.
+    fn claim(&self) -> u64 {
+        if self.spent() {
+            return 0;
+        }
+
+        let attempts = self.attempts.fetch_add(ATTEMPT_CHUNK, Ordering::Relaxed);
+        self.max_attempts.map_or(ATTEMPT_CHUNK, |max| max.saturating_sub(attempts).min(ATTEMPT_CHUNK))
+    }
+
+    fn spent(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+            || self.deadline.is_some_and(|deadline| Instant::now() > deadline)
+            || self.max_attempts.is_some_and(|max| self.attempts.load(Ordering::Relaxed) >= max)
+    }
+
+    /// Why mining stopped without a solution.
+    fn reason(&self) -> MiningError {
+        if self.cancelled.load(Ordering::Relaxed) {
+            MiningError::Cancelled
+        } else if self.exhausted.load(Ordering::Relaxed) {
+            MiningError::NonceSpaceExhausted
+        } else if self.max_attempts.is_some_and(|max| self.attempts.load(Ordering::Relaxed) >= max) {
+            MiningError::MaxAttemptsExceeded
+        } else {
+            MiningError::MaxTimeExceeded
+        }
+    }
+}
+
+struct WakeOnDrop(Thread);
+
+impl Drop for WakeOnDrop {
+    fn drop(&mut self) {
+        self.0.unpark();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rsc_blockdata::{BlockData, block_data::LedgerModel};
+    use crate::rsc_core::shard::MAX_DIFFICULTY;
+    use crate::rsc_error::ErrorCode;
+    use crate::rsc_testing;
+    use std::sync::{Mutex, mpsc};
+
+    /// Shard past its genesis block, asking for given difficulty from then on.
+    fn shard(difficulty: usize) -> (Shard, ByteHash) {
+        let mut shard: Shard = Shard::new();
+        let genesis = rsc_testing::genesis(LedgerModel::Account, None);
+        let hash = genesis.hash;
+
+        shard.push(genesis).unwrap();
+        shard.set_difficulty(difficulty);
+        (shard, hash)
+    }
+
+    fn budget(time: Option<Duration>, attempts: Option<u64>) -> Budget {
+        Budget { time, attempts }
+    }
+
+    /// Waits until the job has asked for `count` templates, giving up after a few seconds.
+    fn wait_for(templates: &Mutex<Vec<Option<ByteHash>>>, count: usize) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while templates.lock().unwrap().len() < count && Instant::now() < deadline {
+            thread::sleep(TIP_POLL_INTERVAL);
+        }
+    }
+
+    #[test]
+    fn single_worker_is_deterministic() {
+        let (shard, tip) = shard(1);
+        let block = Block::new(tip, BlockData::Empty);
+
+        let first = Miner::new(1).mine(&shard, block.clone()).unwrap();
+        let second = Miner::new(1).mine(&shard, block).unwrap();
+        assert_eq!((first.block.nonce, first.block.hash), (second.block.nonce, second.block.hash));
+        assert!(first.block.meets_difficulty(1));
+    }
+
+    #[test]
+    fn budget_cutoff() {
+        let (shard, tip) = shard(MAX_DIFFICULTY);
+        let block = Block::new(tip, BlockData::Empty);
+
+        let time = Miner::new(2).with_budget(budget(Some(Duration::from_millis(50)), None));
+        assert_eq!(time.mine(&shard, block.clone()).unwrap_err().code(), 5001);
+
+        let attempts = Miner::new(2).with_budget(budget(None, Some(10_000)));
+        assert_eq!(attempts.mine(&shard, block).unwrap_err().code(), 5002);
+    }
+
+    #[test]
+    fn job_cancelled() {
+        let (shard, _) = shard(MAX_DIFFICULTY);
+        let job = Miner::new(2).start(&shard, |tip| Block::new(tip.unwrap_or_default(), BlockData::Empty));
+
+        job.cancel();
+        assert_eq!(job.wait().unwrap_err().code(), 5003);
+    }
+
+    #[test]
+    fn job_gives_up_once_budget_spent() {
+        let (shard, _) = shard(MAX_DIFFICULTY);
+        let miner = Miner::new(2).with_budget(budget(None, Some(10_000)));
+        let job = miner.start(&shard, |tip| Block::new(tip.unwrap_or_default(), BlockData::Empty));
+
+        assert_eq!(job.wait().unwrap_err().code(), 5002);
+    }
+
+    #[test]
+    fn job_restarts_on_new_tip() {
+        let (mut shard, genesis) = shard(0);
+        let templates = Arc::new(Mutex::new(Vec::new()));
+        let (release, gate) = mpsc::channel::<()>();
+
+        shard.set_difficulty(MAX_DIFFICULTY);
+        let job = Miner::new(2).start(&shard, {
+            let templates = templates.clone();
+            move |tip| {
+                let first = {
+                    let mut templates = templates.lock().unwrap();
+                    templates.push(tip);
+                    templates.len() == 1
+                };
+
+                // Hold the first round until the tip has moved on, so the job never sees the easier difficulty.
+                if first {
+                    gate.recv().unwrap();
+                }
+                Block::new(tip.unwrap_or_default(), BlockData::Empty)
+            }
+        });
+
+        wait_for(&templates, 1);
+        shard.set_difficulty(0);
+        let next = rsc_testing::block(genesis, BlockData::Empty);
+        let hash = next.hash;
+        shard.push(next).unwrap();
+        shard.set_difficulty(MAX_DIFFICULTY);
+        release.send(()).unwrap();
+
+        wait_for(&templates, 2);
+
+        job.cancel();
+        assert_eq!(job.wait().unwrap_err().code(), 5003);
+        assert_eq!(*templates.lock().unwrap(), vec![Some(genesis), Some(hash)]);
+    }
+
+    #[test]
+    fn nonce_space_rolls_over_into_extra_nonce() {
+        let (shard, tip) = shard(1);
+        let block = Block::new(tip, BlockData::Empty);
+
+        let solution = Miner::new(1).with_max_nonce(3).mine(&shard, block.clone()).unwrap();
+        assert!(solution.block.nonce <= 3);
+        assert!(solution.block.meets_difficulty(1));
+
+        let (shard, _) = self::shard(MAX_DIFFICULTY);
+        let last = Block { extra_nonce: Nonce::MAX - 2, ..block };
+        let error = Miner::new(1).with_max_nonce(3).mine(&shard, last).unwrap_err();
+        assert_eq!(error.code(), 5004);
+    }
+}
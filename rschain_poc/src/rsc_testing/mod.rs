@@ -0,0 +1,86 @@
+//! Fixtures shared by unit tests: genesis blocks, wallets with their keys and signed blocks.
+
+use crate::rsc_blockdata::{BlockData, block_data::{GenesisData, LedgerModel, MonetaryPolicyConfig, TransactionData, Lock, WalletData}};
+use crate::rsc_core::block::Block;
+use crate::rsc_crypto::{keystore::WalletKey, payload::SigningPayload, signature::Scheme};
+use crate::rsc_bank::currency::NATIVE_CURRENCY;
+use crate::rsc_util::{address::{Address, Network}, hash::{ByteHash, Hashable}, hasher::HashAlgorithm};
+
+pub const CHAIN_ID: &str = "rschain-test";
+pub const NETWORK: Network = Network::Test;
+
+pub fn genesis_data(ledger: LedgerModel, policy: Option<MonetaryPolicyConfig>) -> GenesisData {
+    GenesisData { ledger, policy, chain_id: CHAIN_ID.to_string(), network: NETWORK, hasher: HashAlgorithm::default() }
+}
+
+pub fn genesis(ledger: LedgerModel, policy: Option<MonetaryPolicyConfig>) -> Block {
+    block(ByteHash::new(), BlockData::Genesis(genesis_data(ledger, policy)))
+}
+
+/// Block on top of `previous`, hashed so a shard without difficulty accepts it.
+pub fn block(previous: ByteHash, data: BlockData) -> Block {
+    let mut block = Block::new(previous, data);
+    block.update_nonce(0, block.hash_algorithm(HashAlgorithm::default()));
+    block
+}
+
+/// Block at given time, otherwise like `block`.
+pub fn block_at(previous: ByteHash, data: BlockData, timestamp: u64) -> Block {
+    let mut block = Block::new(previous, data);
+    block.timestamp = timestamp;
+    block.update_nonce(0, block.hash_algorithm(HashAlgorithm::default()));
+    block
+}
+
+/// Wallet along with its private key.
+pub struct TestWallet {
+    pub key: WalletKey,
+    pub hash: ByteHash,
+}
+
+impl TestWallet {
+    pub fn new() -> TestWallet {
+        Self::with_scheme(Scheme::Ed25519)
+    }
+
+    pub fn with_scheme(scheme: Scheme) -> TestWallet {
+        let key = WalletKey::generate(scheme).unwrap();
+        let hash = key.wallet_data().unwrap().hash();
+
+        TestWallet { key, hash }
+    }
+
+    pub fn data(&self) -> WalletData {
+        self.key.wallet_data().unwrap()
+    }
+
+    pub fn register(&self, previous: ByteHash) -> Block {
+        block(previous, BlockData::Wallet(self.data()))
+    }
+
+    pub fn address(&self) -> Address {
+        self.hash.to_address(NETWORK)
+    }
+
+    pub fn transfer(&self, to: &TestWallet, amount: f64, lock: Option<Lock>) -> BlockData {
+        BlockData::Transaction(TransactionData { from: self.address(), to: to.address(), currency: NATIVE_CURRENCY, amount, lock })
+    }
+
+    pub fn signature(&self, sequence: u64, data: &BlockData) -> String {
+        hex::encode(self.key.sign(&SigningPayload::new(CHAIN_ID).build(sequence, data)).unwrap())
+    }
+
+    /// Block carrying the data signed by this wallet as its block number `sequence`, counting from zero.
+    pub fn signed(&self, previous: ByteHash, sequence: u64, data: BlockData) -> Block {
+        self.signed_at(previous, sequence, data, crate::rsc_core::block::now())
+    }
+
+    pub fn signed_at(&self, previous: ByteHash, sequence: u64, data: BlockData, timestamp: u64) -> Block {
+        let mut block = Block::new(previous, data);
+        block.timestamp = timestamp;
+        block.sequence = sequence;
+        block.signature = self.signature(sequence, &block.data);
+        block.update_nonce(0, block.hash_algorithm(HashAlgorithm::default()));
+        block
+    }
+}
@@ -0,0 +1,138 @@
+use core::fmt;
+use std::str::FromStr;
+
+use bech32::{primitives::decode::CheckedHrpstring, Bech32m, Hrp};
+use serde::{Deserialize, Serialize};
+
+use super::hash::{ByteHash, HashError};
+
+/// Network an address belongs to, told apart by the address prefix.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Network {
+    #[default]
+    Main,
+    Test,
+}
+
+impl Network {
+    pub fn prefix(&self) -> &'static str {
+        match self {
+            Network::Main => "rsc",
+            Network::Test => "trsc",
+        }
+    }
+
+    fn from_prefix(prefix: &str) -> Option<Network> {
+        [Network::Main, Network::Test].into_iter().find(|n| n.prefix() == prefix)
+    }
+}
+
+/// Wallet hash encoded as bech32m behind a network prefix, so a mistyped address fails its checksum.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Address {
+    network: Network,
+    hash: ByteHash,
+}
+
+impl Address {
+    pub fn new(network: Network, hash: ByteHash) -> Address {
+        Address { network, hash }
+    }
+
+    pub fn network(&self) -> Network {
+        self.network
+    }
+
+    pub fn hash(&self) -> ByteHash {
+        self.hash
+    }
+}
+
+impl FromStr for Address {
+    type Err = HashError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let checked = CheckedHrpstring::new::<Bech32m>(s).map_err(|_| HashError::InvalidAddress)?;
+        let network = Network::from_prefix(&checked.hrp().to_lowercase()).ok_or(HashError::InvalidAddress)?;
+        let hash: ByteHash = checked.byte_iter().collect::<Vec<u8>>().try_into()?;
+
+        Ok(Address { network, hash })
+    }
+}
+
+impl TryFrom<String> for Address {
+    type Error = HashError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<Address> for String {
+    fn from(address: Address) -> Self {
+        address.to_string()
+    }
+}
+
+impl From<Address> for ByteHash {
+    fn from(address: Address) -> Self {
+        address.hash
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let hrp = Hrp::parse_unchecked(self.network.prefix());
+        bech32::encode_lower_to_fmt::<Bech32m, _>(f, hrp, &self.hash.to_ne_bytes()).map_err(|_| fmt::Error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHARSET: &str = "qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+    fn address(network: Network) -> Address {
+        let hash: ByteHash = (0..32).collect::<Vec<u8>>().try_into().unwrap();
+        Address::new(network, hash)
+    }
+
+    #[test]
+    fn round_trip() {
+        for network in [Network::Main, Network::Test] {
+            let address = address(network);
+            let encoded = address.to_string();
+            assert!(encoded.starts_with(&format!("{}1", network.prefix())));
+
+            assert_eq!(encoded.parse::<Address>().unwrap(), address);
+            assert_eq!(encoded.to_uppercase().parse::<Address>().unwrap(), address);
+            assert_eq!(serde_json::from_str::<Address>(&serde_json::to_string(&address).unwrap()).unwrap(), address);
+        }
+    }
+
+    #[test]
+    fn single_character_typo_fails_checksum() {
+        let encoded = address(Network::Main).to_string();
+        let data = encoded.find('1').unwrap() + 1;
+
+        for (position, original) in encoded.char_indices().skip(data) {
+            for typo in CHARSET.chars().filter(|c| *c != original) {
+                let mut mistyped = encoded.clone();
+                mistyped.replace_range(position..=position, &typo.to_string());
+                assert!(matches!(mistyped.parse::<Address>(), Err(HashError::InvalidAddress)), "{}", mistyped);
+            }
+        }
+    }
+
+    #[test]
+    fn networks_told_apart_by_prefix() {
+        let (main, test) = (address(Network::Main), address(Network::Test));
+        assert_ne!(main.to_string(), test.to_string());
+        assert_eq!(test.to_string().parse::<Address>().unwrap().network(), Network::Test);
+
+        let unknown = bech32::encode_lower::<Bech32m>(Hrp::parse_unchecked("xrsc"), &main.hash().to_ne_bytes()).unwrap();
+        assert!(matches!(unknown.parse::<Address>(), Err(HashError::InvalidAddress)));
+    }
+}
@@ -3,19 +3,33 @@ use serde::{Deserialize, Serialize};
 use thiserror;
 use std::ops::Index;
 
-use sha2::digest::generic_array::{GenericArray, ArrayLength};
+use sha2::{digest::Output, Sha256};
+
+use super::{address::{Address, Network}, hasher::HashAlgorithm};
 
 #[derive(thiserror::Error, Debug)]
 pub enum HashError {
     #[error("Invalid size")]
     InvalidSize,
+
+    #[error("Invalid hex")]
+    InvalidHex,
+
+    #[error("Invalid address")]
+    InvalidAddress,
 }
 
-#[derive(Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[derive(Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct ByteHash {
     data: [u8; 32]
 }
 
+impl Default for ByteHash {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl ByteHash {
     pub fn new() -> ByteHash {
         ByteHash { data: [0; 32] }
@@ -24,36 +38,42 @@ impl ByteHash {
     pub fn to_ne_bytes(&self) -> [u8; 32] {
         self.data
     }
+
+    /// Checksummed address of the hash on given network.
+    pub fn to_address(&self, network: Network) -> Address {
+        Address::new(network, *self)
+    }
+
+    pub fn parse_address(address: &str) -> Result<(Network, ByteHash), HashError> {
+        let address: Address = address.parse()?;
+        Ok((address.network(), address.hash()))
+    }
 }
 
 impl TryFrom<Vec<u8>> for ByteHash {
-    type Error = anyhow::Error;
+    type Error = HashError;
 
-    fn try_from(value: Vec<u8>) -> anyhow::Result<Self> {
-        Ok(value
+    fn try_from(value: Vec<u8>) -> Result<Self, Self::Error> {
+        value
             .try_into()
             .map(|data| ByteHash { data })
-            .map_err(|_| HashError::InvalidSize)?)
+            .map_err(|_| HashError::InvalidSize)
     }
 }
 
-impl<T: ArrayLength<u8>> TryFrom<GenericArray<u8, T>> for ByteHash {
+impl TryFrom<Output<Sha256>> for ByteHash {
     type Error = HashError;
 
-    fn try_from(value: GenericArray<u8, T>) -> Result<Self, Self::Error> {
-        Ok(value
-            .as_slice()
-            .try_into()
-            .map(|data| ByteHash { data })
-            .map_err(|_| HashError::InvalidSize)?)
+    fn try_from(value: Output<Sha256>) -> Result<Self, Self::Error> {
+        Ok(ByteHash { data: value.into() })
     }
 }
 
 impl TryFrom<&String> for ByteHash {
-    type Error = anyhow::Error;
+    type Error = HashError;
 
     fn try_from(value: &String) -> Result<Self, Self::Error> {
-        hex::decode(value).and_then(|data| Ok(data.try_into()))?
+        hex::decode(value).map_err(|_| HashError::InvalidHex)?.try_into()
     }
 }
 
@@ -64,7 +84,12 @@ impl From<ByteHash> for String {
 }
 
 pub trait Hashable {
-    fn hash(&self) -> ByteHash;
+    fn hash_with(&self, algorithm: HashAlgorithm) -> ByteHash;
+
+    /// Hash under the default algorithm, the one chains use unless their genesis block selects another.
+    fn hash(&self) -> ByteHash {
+        self.hash_with(HashAlgorithm::default())
+    }
 }
 
 impl fmt::Display for ByteHash {
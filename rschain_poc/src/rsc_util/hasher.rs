@@ -0,0 +1,99 @@
+use serde::{Deserialize, Serialize};
+use sha2::Digest;
+
+use super::hash::ByteHash;
+
+/// Hash function a chain uses for block and data hashes, selected by its genesis block.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HashAlgorithm {
+    #[default]
+    Sha256,
+    Sha3_256,
+    Blake3,
+}
+
+impl HashAlgorithm {
+    pub fn hasher(&self) -> Hasher {
+        match self {
+            HashAlgorithm::Sha256 => Hasher::Sha256(Sha256Hasher::default()),
+            HashAlgorithm::Sha3_256 => Hasher::Sha3_256(Box::default()),
+            HashAlgorithm::Blake3 => Hasher::Blake3(Box::default()),
+        }
+    }
+
+    pub fn digest(&self, data: impl AsRef<[u8]>) -> ByteHash {
+        let mut hasher = self.hasher();
+        hasher.update(data);
+        hasher.finalize()
+    }
+}
+
+/// Incremental hash function with a 32 byte output.
+pub trait ChainHasher {
+    fn update(&mut self, data: impl AsRef<[u8]>);
+    fn finalize(self) -> ByteHash;
+}
+
+#[derive(Default)]
+pub struct Sha256Hasher(sha2::Sha256);
+
+impl ChainHasher for Sha256Hasher {
+    fn update(&mut self, data: impl AsRef<[u8]>) {
+        self.0.update(data);
+    }
+
+    fn finalize(self) -> ByteHash {
+        self.0.finalize().try_into().expect("hasher/Hash incompat")
+    }
+}
+
+#[derive(Default)]
+pub struct Sha3Hasher(sha3::Sha3_256);
+
+impl ChainHasher for Sha3Hasher {
+    fn update(&mut self, data: impl AsRef<[u8]>) {
+        self.0.update(data);
+    }
+
+    fn finalize(self) -> ByteHash {
+        self.0.finalize().try_into().expect("hasher/Hash incompat")
+    }
+}
+
+#[derive(Default)]
+pub struct Blake3Hasher(blake3::Hasher);
+
+impl ChainHasher for Blake3Hasher {
+    fn update(&mut self, data: impl AsRef<[u8]>) {
+        self.0.update(data.as_ref());
+    }
+
+    fn finalize(self) -> ByteHash {
+        self.0.finalize().as_bytes().to_vec().try_into().expect("hasher/Hash incompat")
+    }
+}
+
+/// Hasher of whichever algorithm a chain selected.
+pub enum Hasher {
+    Sha256(Sha256Hasher),
+    Sha3_256(Box<Sha3Hasher>),
+    Blake3(Box<Blake3Hasher>),
+}
+
+impl ChainHasher for Hasher {
+    fn update(&mut self, data: impl AsRef<[u8]>) {
+        match self {
+            Hasher::Sha256(hasher) => hasher.update(data),
+            Hasher::Sha3_256(hasher) => hasher.update(data),
+            Hasher::Blake3(hasher) => hasher.update(data),
+        }
+    }
+
+    fn finalize(self) -> ByteHash {
+        match self {
+            Hasher::Sha256(hasher) => hasher.finalize(),
+            Hasher::Sha3_256(hasher) => (*hasher).finalize(),
+            Hasher::Blake3(hasher) => (*hasher).finalize(),
+        }
+    }
+}
@@ -1 +1,3 @@
 pub mod hash;
+pub mod address;
+pub mod hasher;